@@ -0,0 +1,107 @@
+//! Longest-prefix-match membership test over a set of allowlisted prefixes.
+//!
+//! Used to decide whether an attacker source falls under one of the prefixes
+//! the `allowlist` table recorded for a given configuration, so mitigation
+//! tooling can single out exactly the sources that configuration would not
+//! have protected.
+
+use std::net::IpAddr;
+
+use ipnetwork::IpNetwork;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct PackedPrefix<const N: usize> {
+    addr: [u8; N],
+    pfxlen: u8,
+}
+
+impl<const N: usize> PackedPrefix<N> {
+    fn bit(&self, index: u8) -> usize {
+        let byte = self.addr[(index / 8) as usize];
+        ((byte >> (7 - (index % 8))) & 1) as usize
+    }
+}
+
+#[derive(Debug, Default)]
+struct TrieNode {
+    is_prefix_end: bool,
+    children: [Option<Box<TrieNode>>; 2],
+}
+
+#[derive(Debug, Default)]
+struct PrefixTrie<const N: usize> {
+    root: TrieNode,
+}
+
+impl<const N: usize> PrefixTrie<N> {
+    fn insert(&mut self, prefix: PackedPrefix<N>) {
+        let mut node = &mut self.root;
+        for i in 0..prefix.pfxlen {
+            node = node.children[prefix.bit(i)].get_or_insert_with(Default::default);
+        }
+        node.is_prefix_end = true;
+    }
+
+    fn contains(&self, addr: &[u8; N]) -> bool {
+        let mut node = &self.root;
+        if node.is_prefix_end {
+            return true;
+        }
+        for i in 0..(N as u8 * 8) {
+            let byte = addr[(i / 8) as usize];
+            let bit = ((byte >> (7 - (i % 8))) & 1) as usize;
+            match &node.children[bit] {
+                Some(child) => {
+                    node = child;
+                    if node.is_prefix_end {
+                        return true;
+                    }
+                }
+                None => return false,
+            }
+        }
+        false
+    }
+}
+
+/// Longest-prefix-match set of allowlisted prefixes.
+#[derive(Debug, Default)]
+pub struct AllowlistSet {
+    v4: PrefixTrie<4>,
+    v6: PrefixTrie<16>,
+}
+
+impl AllowlistSet {
+    /// Build a set from the prefixes recorded in the `allowlist` table for one configuration.
+    pub fn from_networks(networks: impl IntoIterator<Item = IpNetwork>) -> Self {
+        let mut set = Self::default();
+        for network in networks {
+            match network {
+                IpNetwork::V4(network) => set.v4.insert(PackedPrefix {
+                    addr: network.network().octets(),
+                    pfxlen: network.prefix(),
+                }),
+                IpNetwork::V6(network) => set.v6.insert(PackedPrefix {
+                    addr: network.network().octets(),
+                    pfxlen: network.prefix(),
+                }),
+            }
+        }
+        set
+    }
+
+    /// Return `true` only if `addr` is covered by some allowlisted prefix.
+    pub fn contains(&self, addr: IpAddr) -> bool {
+        match addr {
+            IpAddr::V4(addr) => self.v4.contains(&addr.octets()),
+            IpAddr::V6(addr) => self.v6.contains(&addr.octets()),
+        }
+    }
+}
+
+#[test]
+fn test_allowlist_set_longest_prefix_match() {
+    let set = AllowlistSet::from_networks(["198.51.100.0/24".parse().unwrap()]);
+    assert!(set.contains("198.51.100.53".parse().unwrap()));
+    assert!(!set.contains("203.0.113.53".parse().unwrap()));
+}
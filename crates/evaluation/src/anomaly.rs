@@ -0,0 +1,78 @@
+//! Multivariate-normal anomaly scoring over a source's packet-rate history.
+//!
+//! An alternative to the flat low-pass/token-bucket caps and the
+//! `train * above_train_limit` ratio: builds a feature vector from a
+//! source's packet rate across the available training-window lengths, fits
+//! a Gaussian over the allowlisted population, and flags a source whose own
+//! feature vector falls outside the density implied by a confidence level,
+//! via its squared Mahalanobis distance to the fitted mean.
+
+use nalgebra::{DMatrix, DVector};
+use statrs::distribution::{ChiSquared, ContinuousCDF as _};
+
+/// Regularization added to the sample covariance's diagonal, scaled by its trace, whenever it is
+/// singular or under-determined (fewer observations than dimensions).
+const COVARIANCE_REGULARIZATION: f64 = 1e-6;
+
+/// Gaussian population model fitted over allowlisted sources' feature vectors.
+#[derive(Debug, Clone)]
+pub struct MahalanobisModel {
+    mean: DVector<f64>,
+    precision: DMatrix<f64>,
+    /// Squared-distance a source's own distance must stay under to be considered ordinary: the
+    /// chi-squared quantile for this model's dimensionality at the configured confidence level.
+    threshold: f64,
+}
+
+impl MahalanobisModel {
+    /// Fit a model from `observations`, one feature vector per allowlisted source.
+    ///
+    /// Returns `None` if there are no observations to fit from. The sample covariance is
+    /// regularized with `λI` whenever there are fewer observations than dimensions, and further
+    /// regularized if it still turns out singular, since a plain sample covariance is not
+    /// invertible in either case.
+    pub fn fit(observations: &[DVector<f64>], confidence_level: f64) -> Option<Self> {
+        let dims = observations.first()?.len();
+        let n = observations.len() as f64;
+
+        let mean =
+            observations.iter().fold(DVector::zeros(dims), |acc, x| acc + x) / n;
+
+        let mut covariance = DMatrix::zeros(dims, dims);
+        for x in observations {
+            let centered = x - &mean;
+            covariance += &centered * centered.transpose();
+        }
+        covariance /= n.max(1.);
+        let trace = covariance.trace().max(f64::EPSILON);
+
+        let mut regularization = (n < dims as f64).then(|| COVARIANCE_REGULARIZATION * trace);
+        let precision = loop {
+            let candidate = match regularization {
+                None => covariance.clone(),
+                Some(lambda) => &covariance + DMatrix::identity(dims, dims) * lambda,
+            };
+            if let Some(inv) = candidate.try_inverse() {
+                break inv;
+            }
+            regularization = Some(regularization.map_or(COVARIANCE_REGULARIZATION * trace, |l| l * 10.));
+        };
+
+        let threshold = ChiSquared::new(dims as f64)
+            .expect("dimensionality of a non-empty feature vector is always positive")
+            .inverse_cdf(confidence_level);
+
+        Some(Self { mean, precision, threshold })
+    }
+
+    /// Squared Mahalanobis distance of `x` to this model's fitted mean.
+    pub fn score(&self, x: &DVector<f64>) -> f64 {
+        let centered = x - &self.mean;
+        (centered.transpose() * &self.precision * &centered)[(0, 0)]
+    }
+
+    /// `true` if `x` falls outside the density implied by the configured confidence level.
+    pub fn is_anomalous(&self, x: &DVector<f64>) -> bool {
+        self.score(x) > self.threshold
+    }
+}
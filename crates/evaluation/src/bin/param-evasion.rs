@@ -88,6 +88,7 @@ struct AlgorithmParameters {
     pub min_active: u8,
     pub min_pkts_avg: u32,
     pub low_pass: u32,
+    pub token_bucket: Option<evaluation::TokenBucket>,
     pub above_train_limit: u8,
     pub attack_bandwidth: u64,
 }
@@ -211,8 +212,11 @@ fn load_filebatch(
                 test_length: config.test_length,
                 min_active: config.min_active,
                 min_pkts_avg: config.min_pkts_avg,
-                low_pass: config.low_pass,
-                above_train_limit: config.above_train_limit.round() as u8,
+                // The grid currently sweeps the same value for both address families, so either
+                // side of the `PerFamily` carries the parameter that actually varies here.
+                low_pass: config.low_pass.v4,
+                token_bucket: config.token_bucket,
+                above_train_limit: config.above_train_limit.v4.round() as u8,
                 attack_bandwidth,
             };
             let results = eval_results.entry(params).or_default();
@@ -6,9 +6,23 @@
 //! This is a JSON file with a weighted list of attacker IP addresses like `{"198.51.100.100": 1.0, "198.51.100.101": 2.0}`.
 //! Second a file describing the catchment area of all locations is needed, which tells for an IP to which location it routes.
 //! The file is a JSON array containing these elements.
-//! The first list contains the IP ranges which have this catchment behavior.
+//! The first list contains the IP ranges which have this catchment behavior, IPv4 and IPv6 alike.
 //! The second object describes for each anycast IP address, which locations receive which fraction of the traffic.
 //!
+//! Both address families run through the same grid: locations/destinations are fetched for both
+//! from the database, and attacker sources are aggregated to a family-appropriate prefix
+//! (`/24` for IPv4, `/48` for IPv6) before being weighted, rather than one shared prefix length.
+//!
+//! Per-source admission decisions are optionally followed by a global `bandwidth_budget`
+//! reconciliation pass modeling a finite mitigation/link capacity: once summed admitted traffic
+//! crosses a configurable fraction of the budget, every source's admitted volume is downgraded
+//! proportionally and the shed excess is reclassified accordingly.
+//!
+//! `DataConfiguration`'s `low_pass` and `above_train_limit` thresholds are carried per address
+//! family (`PerFamily`), though the grid currently sweeps the same value for both; and each
+//! `EvaluationResults` additionally reports a `by_family` breakdown, so IPv6 mitigation quality
+//! can be assessed separately from IPv4.
+//!
 //! ```json
 //! [
 //!     [
@@ -27,22 +41,34 @@
 //! ]
 //! ```
 //!
+//! Optionally, attacker weight can instead (or additionally) be given per origin AS with
+//! `--attacker-asns`, a JSON file like `{"AS64500": 3.0}`, together with `--rib-dump`, an MRT
+//! `TABLE_DUMP_V2` RIB dump used to expand each ASN into its announced prefixes.
+//!
 //! The values for the parameter combinations can be changed by editing the constants in the source code.
 //!
-//! The result are many files with the given filename pattern: `eval_results_{location}_{ip_dst}_{attacker_bps}bps.json`.
+//! The result are many files with the given filename pattern:
+//! `eval_results_{location}_{ip_dst}_{attacker_bps}bps.ndjson`. Each file is newline-delimited
+//! JSON: one `[DataConfiguration, EvaluationResults]` pair per line, written as soon as that
+//! configuration finishes evaluating rather than buffered into one giant array, since a full
+//! grid's results no longer fit comfortably in memory at once.
 
 #![deny(unused_import_braces, unused_qualifications)]
 
 use color_eyre::eyre::{eyre, Context as _, Result};
-use evaluation::{ok, DataConfiguration, EvaluationResults, Location};
+use evaluation::anomaly::MahalanobisModel;
+use evaluation::catchment::CatchmentTable;
+use evaluation::{
+    ok, BandwidthBudget, DataConfiguration, EvaluationResults, FamilyTotals, Location,
+    MahalanobisConfig, PerFamily,
+};
 use futures::stream::{StreamExt as _, TryStreamExt as _};
-use ipnetwork::Ipv4Network;
+use nalgebra::DVector;
 use sqlx::postgres::PgConnectOptions;
-use sqlx::types::ipnetwork::IpNetwork;
+use sqlx::types::ipnetwork::{IpNetwork, Ipv4Network, Ipv6Network};
 use sqlx::{ConnectOptions as _, PgPool};
-use std::cmp::Ordering;
 use std::collections::{BTreeMap, BTreeSet};
-use std::net::IpAddr;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 use std::path::PathBuf;
 use std::str::FromStr as _;
 use std::sync::Arc;
@@ -69,11 +95,54 @@ static MIN_ACTIVE_PERIODS: [u8; 4] = [1, 4, 8, 12];
 static MIN_PKTS_AVG: [u32; 3] = [/* 4, 8, 16, 32, */ 64, 128, 256];
 /// Low pass filter of traffic allowed while not on the allowlist
 static LOW_PASS_FILTER: [u32; 4] = [128, 512, 2048, 8192];
+/// Token-bucket (burst capacity, refill rate) parameterizations evaluated
+/// alongside the flat `LOW_PASS_FILTER` caps. `None` keeps the flat cap.
+static TOKEN_BUCKETS: [Option<evaluation::TokenBucket>; 3] = [
+    None,
+    Some(evaluation::TokenBucket {
+        capacity: 512,
+        refill_rate: 64,
+    }),
+    Some(evaluation::TokenBucket {
+        capacity: 2048,
+        refill_rate: 256,
+    }),
+];
+/// Confidence levels for the Mahalanobis-distance anomaly-scoring mode, evaluated alongside the
+/// flat `train * above_train_limit` ratio test. `None` keeps the ratio test.
+static ANOMALY_CONFIGS: [Option<MahalanobisConfig>; 2] = [
+    None,
+    Some(MahalanobisConfig {
+        confidence_level: ordered_float::OrderedFloat(0.99),
+    }),
+];
+/// Prefix length sources are aggregated to before being treated as one
+/// attacker in `create_weighted_attack_traffic`, matching how deployed
+/// limiters group IPv6 clients into prefixes rather than per address.
+const SOURCE_AGGREGATION_PREFIX_V4: u8 = 24;
+const SOURCE_AGGREGATION_PREFIX_V6: u8 = 48;
+/// Same aggregation prefixes, applied to the window cache's train/test traffic and recorded on
+/// every `DataConfiguration` so `evaluate_configuration` can report which granularity its results
+/// assume. Collapsing IPv6 sources at their native per-address granularity would explode the
+/// allowlist, so IPv6 aggregates at a far coarser prefix than IPv4.
+const AGGREGATION_PREFIX: PerFamily<u8> = PerFamily {
+    v4: SOURCE_AGGREGATION_PREFIX_V4,
+    v6: SOURCE_AGGREGATION_PREFIX_V6,
+};
 /// Total bandwidth for the attacker
 #[allow(clippy::identity_op)]
 static ATTACKER_TOTAL_TRAFFIC_BITS_PER_SECOND: [u64; 2] = [40 * GIBIBITS, 100 * TEBIBITS];
 /// How much the training traffic may be exceeded by the traffic in the test window
 static ABOVE_TRAIN_LIMITS: [f64; 3] = [1.0, 2.0, 4.0];
+/// Total admitted-traffic capacities evaluated alongside the unbounded baseline. `None` leaves
+/// admission decisions exactly as computed per source, with no global capacity enforced.
+static BANDWIDTH_BUDGETS: [Option<BandwidthBudget>; 2] = [
+    None,
+    Some(BandwidthBudget {
+        budget: ordered_float::OrderedFloat(512_000.),
+        start_check_fraction: ordered_float::OrderedFloat(0.8),
+    }),
+];
 /// Total number of available time intervals
 static TOTAL_TIME_LENGHT: u32 = 648;
 
@@ -84,14 +153,24 @@ struct CliArgs {
     attacker_ips_file: PathBuf,
     #[clap(long = "catchment")]
     catchment_file: PathBuf,
+    /// JSON file mapping ASN strings like `{"AS64500": 3.0}` to a relative
+    /// weight, expanded into the AS's announced prefixes via `--rib-dump`.
+    #[clap(long = "attacker-asns", requires = "rib_dump")]
+    attacker_asns_file: Option<PathBuf>,
+    /// MRT `TABLE_DUMP_V2` RIB dump used to expand `--attacker-asns` into prefixes.
+    #[clap(long = "rib-dump")]
+    rib_dump: Option<PathBuf>,
     /// Number of evasion IPs
     #[clap(long = "evasion-ips")]
     evasion_ips: Option<usize>,
 }
 
 /// Maps from the attacker controlled IP addresses to the bandwidth assigned to each of them
+///
+/// Bandwidth is stored as `f32`: only the relative magnitude against the low-pass/training
+/// thresholds matters, and this is one of the hottest, most-cloned structures in the grid search.
 #[derive(Clone, Default)]
-struct AttackerTrafficDistribution(pub BTreeMap<IpNetwork, f64>, pub Vec<IpNetwork>);
+struct AttackerTrafficDistribution(pub BTreeMap<IpNetwork, f32>, pub Vec<IpNetwork>);
 
 impl fmt::Debug for AttackerTrafficDistribution {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -121,10 +200,19 @@ async fn main() -> Result<()> {
         .await?;
 
     #[allow(clippy::type_complexity)]
-    let catchment: Vec<(Vec<Ipv4Network>, BTreeMap<IpAddr, BTreeMap<String, f64>>)> = {
+    let catchment: Vec<(Vec<IpNetwork>, BTreeMap<IpAddr, BTreeMap<String, f64>>)> = {
         let catchment_data = std::fs::read_to_string(&args.catchment_file)?;
         serde_json::from_str(&catchment_data)?
     };
+    let catchment_table = {
+        let mut table = CatchmentTable::new();
+        for (idx, (networks, _)) in catchment.iter().enumerate() {
+            for &network in networks {
+                table.insert(network, idx);
+            }
+        }
+        table
+    };
 
     // Fetch all locations and destination combinations
     #[derive(Debug)]
@@ -140,8 +228,6 @@ SELECT DISTINCT
     iprange_dst AS "iprange_dst!"
 FROM
     pre_test_intervals
-WHERE
-    family(iprange_dst) = 4
 ORDER BY
     1,
     2
@@ -153,6 +239,20 @@ ORDER BY
         let attacker_ips = std::fs::read_to_string(&args.attacker_ips_file)?;
         serde_json::from_str(&attacker_ips)?
     };
+    // ASN-keyed weights are expanded into their announced prefixes up front,
+    // so they can be merged with the per-IP weights like any other source.
+    let attacker_asn_networks: BTreeMap<IpNetwork, f64> =
+        match (&args.attacker_asns_file, &args.rib_dump) {
+            (Some(asns_file), Some(rib_file)) => {
+                let asn_weights: BTreeMap<String, f64> =
+                    serde_json::from_str(&std::fs::read_to_string(asns_file)?)?;
+                let rib = evaluation::mrt::AsRibTable::load_mrt_dump(&std::fs::read(rib_file)?)
+                    .map_err(|err| eyre!(err))
+                    .context("Failed to load MRT RIB dump")?;
+                expand_asn_weights(&asn_weights, &rib)
+            }
+            _ => BTreeMap::new(),
+        };
 
     let num_locs_per_dst = {
         let mut num_locs_per_dst = BTreeMap::new();
@@ -171,6 +271,7 @@ ORDER BY
             // Calculate one distribution of attacker IPs
             let mut attacker_traffic = create_weighted_attack_traffic(
                 &attacker_ips,
+                &attacker_asn_networks,
                 attacker_bps,
                 args.evasion_ips.unwrap_or(0),
             );
@@ -180,58 +281,34 @@ ORDER BY
                 .0
                 .into_iter()
                 .filter_map(|(net, bandwidth)| {
-                    if let IpNetwork::V4(netv4) = net {
-                        let catchment_idx =
-                            catchment.binary_search_by(|(catchment_net, _)| {
-                                match (
-                                    catchment_net[0].network().cmp(&netv4.network()),
-                                    catchment_net[catchment_net.len() - 1]
-                                        .broadcast()
-                                        .cmp(&netv4.broadcast()),
-                                ) {
-                                    (Ordering::Less, Ordering::Less) => Ordering::Less,
-                                    (Ordering::Greater, Ordering::Greater) => Ordering::Greater,
-
-                                    (Ordering::Less, Ordering::Equal | Ordering::Greater)
-                                    | (Ordering::Equal, _) => Ordering::Equal,
-
-                                    (Ordering::Greater, Ordering::Less)
-                                    | (Ordering::Greater, Ordering::Equal) => {
-                                        panic!("Nonsensical ordering of networks")
-                                    }
-                                }
-                            });
-
-                        match catchment_idx {
-                            Ok(idx) => {
-                                if let Some(catchment_loc) =
-                                    catchment[idx].1.get(&iprange_dst.network())
-                                {
-                                    // If location is not in the catchment list, then we know that this location never received traffic
-                                    // Therefore filter the source completely
-                                    catchment_loc
-                                        .get(location)
-                                        .map(|catchment_factor| (net, bandwidth * catchment_factor))
-                                } else {
-                                    // In our catchment data the source never send traffic to this destination
-                                    // We can therefore not estimate the catchment and split equal among locations
-                                    Some((net, bandwidth / num_locs_per_dst[&iprange_dst] as f64))
-                                }
-                            }
-                            // No pre-recorded catchment information for this network
-                            // Split traffic equally among all locations
-                            Err(_) => {
-                                Some((net, bandwidth / num_locs_per_dst[&iprange_dst] as f64))
+                    match catchment_table.longest_match(net.network()) {
+                        Some(idx) => {
+                            if let Some(catchment_loc) =
+                                catchment[idx].1.get(&iprange_dst.network())
+                            {
+                                // If location is not in the catchment list, then we know that this location never received traffic
+                                // Therefore filter the source completely
+                                catchment_loc.get(location).map(|catchment_factor| {
+                                    (net, bandwidth * *catchment_factor as f32)
+                                })
+                            } else {
+                                // In our catchment data the source never send traffic to this destination
+                                // We can therefore not estimate the catchment and split equal among locations
+                                Some((net, bandwidth / num_locs_per_dst[&iprange_dst] as f32))
                             }
                         }
-                    } else {
-                        Some((net, bandwidth))
+                        // No pre-recorded catchment information for this network
+                        // Split traffic equally among all locations
+                        None => Some((net, bandwidth / num_locs_per_dst[&iprange_dst] as f32)),
                     }
                 })
                 .collect();
             let attacker_traffic = Arc::new(attacker_traffic);
 
-            let eval_results: Vec<_> = {
+            let (configs, window_cache): (
+                Vec<DataConfiguration<Arc<AttackerTrafficDistribution>>>,
+                &'static BTreeMap<(u32, u8), BTreeMap<IpNetwork, f32>>,
+            ) = {
                 // Given a fixed location and iprange_dst we can pre-fetch all the window information
                 log::info!("Fetch window cache for {} {}", location, iprange_dst);
                 let window_cache: Vec<_> = (1..=TOTAL_TIME_LENGHT)
@@ -240,8 +317,7 @@ ORDER BY
                     .map(|(start, window)| {
                         let pool = pool.clone();
                         async move {
-                            ok((
-                                (start, window),
+                            let traffic_by_family: PerFamily<BTreeMap<IpNetwork, f32>> =
                                 tokio::spawn(fetch_traffic_interval(
                                     start,
                                     window,
@@ -249,12 +325,20 @@ ORDER BY
                                     iprange_dst,
                                     pool.clone(),
                                 ))
-                                .await??,
-                            ))
+                                .await??;
+                            // Aggregate each family to its configured prefix, then recombine:
+                            // downstream code treats the window cache as one flat per-source map.
+                            let mut traffic =
+                                aggregate_to_prefix(&traffic_by_family.v4, AGGREGATION_PREFIX.v4);
+                            traffic.extend(aggregate_to_prefix(
+                                &traffic_by_family.v6,
+                                AGGREGATION_PREFIX.v6,
+                            ));
+                            ok(((start, window), traffic))
                         }
                     })
                     .collect();
-                let window_cache: Result<BTreeMap<(u32, u8), BTreeMap<IpNetwork, f64>>> =
+                let window_cache: Result<BTreeMap<(u32, u8), BTreeMap<IpNetwork, f32>>> =
                     futures::future::join_all(window_cache)
                         .await
                         .into_iter()
@@ -277,7 +361,7 @@ ORDER BY
                     windows_train = vec![Location::from_str(location)?.best_train_length()];
                 }
 
-                let res = windows_train
+                let res: Vec<DataConfiguration<Arc<AttackerTrafficDistribution>>> = windows_train
                     .into_iter()
                     .flat_map(|train_length| {
                         WINDOWS_TEST.into_iter().flat_map({
@@ -296,11 +380,23 @@ ORDER BY
                                                         let attacker_traffic =
                                                             attacker_traffic.clone();
                                                         move |low_pass| {
+                                                            TOKEN_BUCKETS.into_iter().flat_map({
+                                                                let attacker_traffic =
+                                                                    attacker_traffic.clone();
+                                                                move |token_bucket| {
+                                                            ANOMALY_CONFIGS.into_iter().flat_map({
+                                                                let attacker_traffic =
+                                                                    attacker_traffic.clone();
+                                                                move |anomaly| {
                                                             ABOVE_TRAIN_LIMITS.into_iter().flat_map(
                                                                 {
                                                                     let attacker_traffic =
                                                                         attacker_traffic.clone();
                                                                     move |above_train_limit| {
+                                                            BANDWIDTH_BUDGETS.into_iter().flat_map({
+                                                                let attacker_traffic =
+                                                                    attacker_traffic.clone();
+                                                                move |bandwidth_budget| {
                                                                         (1..=(TOTAL_TIME_LENGHT
                                                                     - train_length as u32
                                                                     - test_length as u32
@@ -318,17 +414,33 @@ ORDER BY
                                                                                 test_length,
                                                                                 min_active,
                                                                                 min_pkts_avg,
-                                                                                low_pass,
+                                                                                low_pass: PerFamily {
+                                                                                    v4: low_pass,
+                                                                                    v6: low_pass,
+                                                                                },
+                                                                                token_bucket,
+                                                                                anomaly,
                                                                                 attacker:
                                                                                     attacker_traffic
                                                                                         .clone(),
-                                                                                above_train_limit: above_train_limit.try_into().unwrap(),
+                                                                                above_train_limit: PerFamily {
+                                                                                    v4: above_train_limit.try_into().unwrap(),
+                                                                                    v6: above_train_limit.try_into().unwrap(),
+                                                                                },
+                                                                                aggregation_prefix: AGGREGATION_PREFIX,
+                                                                                bandwidth_budget,
                                                                             }
                                                                         }
                                                                     })
+                                                                }
+                                                            })
                                                                     }
                                                                 },
                                                             )
+                                                                }
+                                                            })
+                                                                }
+                                                            })
                                                         }
                                                     })
                                                 }
@@ -339,29 +451,22 @@ ORDER BY
                         })
                     })
                     // .take(100_000)
-                    .map({
-                        |data_config: DataConfiguration<Arc<AttackerTrafficDistribution>>| {
-                            let pool = pool.clone();
-                            async move {
-                                ok((
-                                    data_config.clone(),
-                                    evaluate_configuration(data_config, window_cache, pool.clone())
-                                        .await?,
-                                ))
-                            }
-                        }
-                    })
                     .collect();
                 log::info!(
                     "Finished DataConfigurations for {} {}",
                     location,
                     iprange_dst
                 );
-                res
+                (res, window_cache)
             };
+            // Leaked once per `(location, iprange_dst, attacker_bps)` combination: every
+            // evaluation task below borrows its `DataConfiguration` by index out of this slice
+            // instead of cloning the (now much larger) `AttackerTrafficDistribution` per task.
+            let configs: &'static [DataConfiguration<Arc<AttackerTrafficDistribution>>] =
+                Box::leak(configs.into_boxed_slice());
 
             // Run all the futures till completion
-            let num_results = eval_results.len();
+            let num_results = configs.len();
             let progress_bar = indicatif::ProgressBar::with_draw_target(
                 Some(num_results as u64),
                 indicatif::ProgressDrawTarget::stderr_with_hz(1),
@@ -371,33 +476,50 @@ ORDER BY
                  {percent}%",
             )?);
             progress_bar.inc(0);
-            let eval_results: Vec<(DataConfiguration<Arc<AttackerTrafficDistribution>>, EvaluationResults)> =
+
+            // Results are appended to the output file as one NDJSON line per configuration as
+            // soon as it finishes, rather than collected into a single `Vec` first: the full
+            // result set for a grid this size no longer fits comfortably in memory at once.
+            let out_file = std::fs::File::create(format!(
+                "./eval_results_{location}_{ip_dst}_{attacker_bps}bps.ndjson",
+                ip_dst = iprange_dst.network()
+            ))?;
+            let out_file = Arc::new(Mutex::new(std::io::BufWriter::new(out_file)));
+
             // Iterate over all futures
             // Spawn them parallel into the tokio runtime
             // Run a limited set of them in parallel
-            futures::stream::iter(eval_results)
+            futures::stream::iter(0..configs.len())
+                .map(|idx| {
+                    let pool = pool.clone();
+                    async move {
+                        ok((idx, evaluate_configuration(&configs[idx], window_cache, pool).await?))
+                    }
+                })
                 .map(|fut| tokio::spawn(fut))
                 .buffer_unordered(80 * 10)
                 .enumerate()
-                .map(|(idx, v)| {
-                    let (data_config, eval_results) = v??;
+                .try_for_each(|(pos, v)| {
+                    let out_file = out_file.clone();
+                    async move {
+                        let (idx, eval_results) = v??;
+                        let line = serde_json::to_string(&(&configs[idx], eval_results))?;
+                        {
+                            use std::io::Write as _;
+                            writeln!(out_file.lock().await, "{line}")?;
+                        }
 
-                    if idx % 100 == 0 {
-                        progress_bar.inc(100)
-                    };
-                    ok((data_config, eval_results))
+                        if pos % 100 == 0 {
+                            progress_bar.inc(100)
+                        };
+                        ok(())
+                    }
                 })
-                .try_collect()
                 .await?;
-
-            // Save the results
-            std::fs::write(
-                format!(
-                    "./eval_results_{location}_{ip_dst}_{attacker_bps}bps.json",
-                    ip_dst = iprange_dst.network()
-                ),
-                serde_json::to_string(&eval_results)?,
-            )?;
+            {
+                use std::io::Write as _;
+                out_file.lock().await.flush()?;
+            }
         }
     }
 
@@ -431,18 +553,49 @@ ORDER BY
 //     AttackerTrafficDistribution(attacker_traffic)
 // }
 
+/// Expand ASN-keyed weights (e.g. `{"AS64500": 3.0}`) into the AS's
+/// announced prefixes, splitting each ASN's weight evenly across them. ASNs
+/// which announce nothing in `rib` are ignored, since there is no prefix to
+/// attribute their weight to.
+fn expand_asn_weights(
+    asn_weights: &BTreeMap<String, f64>,
+    rib: &evaluation::mrt::AsRibTable,
+) -> BTreeMap<IpNetwork, f64> {
+    let mut networks = BTreeMap::new();
+    for (asn, &weight) in asn_weights {
+        let Ok(asn) = asn.strip_prefix("AS").unwrap_or(asn).parse::<u32>() else {
+            log::warn!("Ignoring malformed ASN key in attacker ASN weights: {asn}");
+            continue;
+        };
+        // The RIB holds one entry per peer, so the same prefix can be listed several times.
+        let prefixes: BTreeSet<IpNetwork> = rib.prefixes_for_asn(asn).iter().copied().collect();
+        if prefixes.is_empty() {
+            log::warn!("AS{asn} announces no prefixes in the RIB dump, ignoring its weight");
+            continue;
+        }
+        let weight_per_prefix = weight / prefixes.len() as f64;
+        for network in prefixes {
+            *networks.entry(network).or_insert(0.) += weight_per_prefix;
+        }
+    }
+    networks
+}
+
 /// Create a [`AttackerTrafficDistribution`] from a set of weighted sources and a total traffic amount.
 ///
 /// The argument `source_ips` provides a relative weight between each [`IpAddr`] to indicate
+/// its share of the total traffic; `asn_networks` does the same for prefixes already expanded
+/// from an ASN-keyed weight (see [`expand_asn_weights`]), so they are not re-normalized to `/24`.
 fn create_weighted_attack_traffic(
     source_ips: &BTreeMap<IpAddr, f64>,
+    asn_networks: &BTreeMap<IpNetwork, f64>,
     total_bits_per_second: u64,
     evasion_ips: usize,
 ) -> AttackerTrafficDistribution {
     // 100 Byte packet. It is enough for a 16 Byte query name and all header including ethernet overhead.
     const BITS_PER_PACKET: u64 = 100 * 8;
 
-    let total_weight = source_ips.values().sum::<f64>();
+    let total_weight = source_ips.values().sum::<f64>() + asn_networks.values().sum::<f64>();
 
     let total_bits_per_hour = total_bits_per_second as f64 * 3600.;
 
@@ -453,12 +606,20 @@ fn create_weighted_attack_traffic(
 
     let mut attacker_traffic = BTreeMap::new();
     for (&source_ip, &weight) in source_ips {
-        let source_net = IpNetwork::new(source_ip, 24).expect("Prefix size never exceeds limit.");
+        let prefix = match source_ip {
+            IpAddr::V4(_) => SOURCE_AGGREGATION_PREFIX_V4,
+            IpAddr::V6(_) => SOURCE_AGGREGATION_PREFIX_V6,
+        };
+        let source_net =
+            IpNetwork::new(source_ip, prefix).expect("Prefix size never exceeds limit.");
         // Normalize the IpNetwork type
         let source_net =
-            IpNetwork::new(source_net.network(), 24).expect("Prefix size never exceeds limit.");
+            IpNetwork::new(source_net.network(), prefix).expect("Prefix size never exceeds limit.");
         *attacker_traffic.entry(source_net).or_insert(0.) += packets_per_weight * weight;
     }
+    for (&network, &weight) in asn_networks {
+        *attacker_traffic.entry(network).or_insert(0.) += packets_per_weight * weight;
+    }
 
     // Pick a stable subset of the attacker traffic
     // This subset is later used for evasion
@@ -470,6 +631,13 @@ fn create_weighted_attack_traffic(
         evasion_ips,
     );
 
+    // Traffic values only need to distinguish positives from negatives, so store them as f32
+    // to keep the per-configuration memory footprint down.
+    let attacker_traffic: BTreeMap<IpNetwork, f32> = attacker_traffic
+        .into_iter()
+        .map(|(network, weight)| (network, weight as f32))
+        .collect();
+
     AttackerTrafficDistribution(attacker_traffic, attacker_traffic_evasion)
 }
 
@@ -478,8 +646,8 @@ fn create_weighted_attack_traffic(
 /// The database connection is used to retrieve pre-aggregated data from the database.
 /// The cache is read-only and shared amoung multiple `data_config`
 async fn evaluate_configuration(
-    data_config: DataConfiguration<Arc<AttackerTrafficDistribution>>,
-    window_cache: &'static BTreeMap<(u32, u8), BTreeMap<IpNetwork, f64>>,
+    data_config: &DataConfiguration<Arc<AttackerTrafficDistribution>>,
+    window_cache: &'static BTreeMap<(u32, u8), BTreeMap<IpNetwork, f32>>,
     pool: PgPool,
 ) -> Result<EvaluationResults> {
     let mut train_traffic = window_cache
@@ -506,9 +674,13 @@ async fn evaluate_configuration(
         })?
         .clone();
 
-    let allowlist = fetch_allowlist(data_config.clone(), pool.clone())
+    // The join below treats the allowlist as one flat map regardless of family; only the
+    // fetch itself needs the per-family split.
+    let allowlist_by_family = fetch_allowlist(data_config, pool.clone())
         .await
         .context("Failed to fetch allowlist")?;
+    let mut allowlist = allowlist_by_family.v4;
+    allowlist.extend(allowlist_by_family.v6);
 
     // To check evasion we extend the allowlist with entries from the attacker.
     // We also ensure these entries have sufficiently large entries under train_traffic, such that the allowed amount of traffic is not too small.
@@ -524,11 +696,44 @@ async fn evaluate_configuration(
         }
     }
 
+    // Packet rate of `source` across every training-window length available at this
+    // `window_start`, one coordinate per length, used as the feature vector for anomaly scoring.
+    let feature_vector = |source: &IpNetwork| -> DVector<f64> {
+        DVector::from_iterator(
+            WINDOWS_TRAIN.len(),
+            WINDOWS_TRAIN.iter().map(|&w| {
+                window_cache
+                    .get(&(data_config.window_start, w))
+                    .and_then(|traffic| traffic.get(source))
+                    .copied()
+                    .unwrap_or(0.) as f64
+            }),
+        )
+    };
+
+    // When configured, fit the Mahalanobis model once over the allowlisted sources' feature
+    // vectors, rather than per source below.
+    let anomaly_model = data_config.anomaly.and_then(|config| {
+        let observations: Vec<DVector<f64>> = allowlist.keys().map(&feature_vector).collect();
+        MahalanobisModel::fit(&observations, config.confidence_level.0)
+    });
+
     let mut total = 0.;
     let mut true_positives = 0.;
     let mut true_negatives = 0.;
     let mut false_positives = 0.;
     let mut false_negatives = 0.;
+    // Per-source (source, test_admitted, attack_admitted) volumes, i.e. the portion of each
+    // source's traffic that passed the per-IP admission decision below, regardless of whether it
+    // was legitimate or attack traffic. Only collected when a `bandwidth_budget` is configured,
+    // since the global downgrade pass after the loop is the only thing that reads it.
+    let mut admitted_sources: Vec<(IpNetwork, f64, f64)> = Vec::new();
+    // Per-family TP/TN/FP/FN, accumulated by snapshotting the flat counters before and after each
+    // match arm below and crediting the difference to `ipnet`'s family.
+    let mut family_totals = PerFamily {
+        v4: FamilyTotals::default(),
+        v6: FamilyTotals::default(),
+    };
     for (ipnet, values) in giant_merge_join(
         &data_config.attacker.0,
         &allowlist,
@@ -542,22 +747,38 @@ async fn evaluate_configuration(
         }
 
         let values = (
-            values.0.map(|&x| x as f64).unwrap_or(0.),
-            values.1.copied(),
-            values.2.map(|x| x * NETFLOW_SAMPLING_RATE),
-            values.3.map(|x| x * NETFLOW_SAMPLING_RATE).unwrap_or(0.),
+            values.0.map(|x| x as f64).unwrap_or(0.),
+            values.1,
+            values.2.map(|x| x as f64 * NETFLOW_SAMPLING_RATE),
+            values
+                .3
+                .map(|x| x as f64 * NETFLOW_SAMPLING_RATE)
+                .unwrap_or(0.),
         );
 
         // Add the total traffic observed, by summing attack and test traffic
         total += values.0 + values.3;
 
+        let before = (true_positives, true_negatives, false_positives, false_negatives);
+
         match values {
             // Mixed traffic received but the IP is not on the allowlist
             (attack, None, _, test) => {
                 let attack_ratio = attack / (attack + test);
 
+                // Pass-through cap for this window: either the flat low-pass
+                // threshold, or the token-bucket burst-plus-refill allowance
+                // over the test window's duration.
+                let low_pass = match data_config.token_bucket {
+                    Some(bucket) => {
+                        let window_seconds = data_config.test_length as f64 * 3600.;
+                        bucket.capacity as f64 + bucket.refill_rate as f64 * window_seconds
+                    }
+                    None => data_config.low_pass.get(&ipnet) as f64,
+                };
+
                 // Low Pass threshold adjusted by the fraction between test and attack traffic
-                let test_low_pass = data_config.low_pass as f64 * (1. - attack_ratio);
+                let test_low_pass = low_pass * (1. - attack_ratio);
                 if test <= test_low_pass {
                     true_negatives += test;
                 } else {
@@ -565,35 +786,78 @@ async fn evaluate_configuration(
                     false_positives += test - test_low_pass;
                 }
 
-                let attack_low_pass = data_config.low_pass as f64 * attack_ratio;
+                let attack_low_pass = low_pass * attack_ratio;
                 if attack <= attack_low_pass {
                     false_negatives += attack;
                 } else {
                     false_negatives += attack_low_pass;
                     true_positives += attack - attack_low_pass;
                 }
+
+                if data_config.bandwidth_budget.is_some() {
+                    admitted_sources.push((
+                        ipnet,
+                        test.min(test_low_pass),
+                        attack.min(attack_low_pass),
+                    ));
+                }
             }
 
             // Mixed traffic received but the IP is allowed
             (attack, Some(()), Some(train), test) => {
-                let attack_ratio = attack / (attack + test);
-
-                // Training threshold adjusted by the fraction between test and attack traffic
-                let test_train_threshold =
-                    train * data_config.above_train_limit.0 * (1. - attack_ratio);
-                if test <= test_train_threshold {
-                    true_negatives += test;
+                // A source with no recorded training history anywhere (all-zero feature vector)
+                // falls back to the ratio test below instead of being scored against the model.
+                let anomalous = anomaly_model.as_ref().and_then(|model| {
+                    let vector = feature_vector(&ipnet);
+                    vector
+                        .iter()
+                        .any(|&x| x != 0.)
+                        .then(|| model.is_anomalous(&vector))
+                });
+
+                if let Some(anomalous) = anomalous {
+                    if anomalous {
+                        false_positives += test;
+                        true_positives += attack;
+                        if data_config.bandwidth_budget.is_some() {
+                            admitted_sources.push((ipnet, 0., 0.));
+                        }
+                    } else {
+                        true_negatives += test;
+                        false_negatives += attack;
+                        if data_config.bandwidth_budget.is_some() {
+                            admitted_sources.push((ipnet, test, attack));
+                        }
+                    }
                 } else {
-                    true_negatives += test_train_threshold;
-                    false_positives += test - test_train_threshold;
-                }
+                    let attack_ratio = attack / (attack + test);
 
-                let attack_train_threshold = train * data_config.above_train_limit.0 * attack_ratio;
-                if attack <= attack_train_threshold {
-                    false_negatives += attack;
-                } else {
-                    false_negatives += attack_train_threshold;
-                    true_positives += attack - attack_train_threshold;
+                    // Training threshold adjusted by the fraction between test and attack traffic
+                    let test_train_threshold =
+                        train * data_config.above_train_limit.get(&ipnet).0 * (1. - attack_ratio);
+                    if test <= test_train_threshold {
+                        true_negatives += test;
+                    } else {
+                        true_negatives += test_train_threshold;
+                        false_positives += test - test_train_threshold;
+                    }
+
+                    let attack_train_threshold =
+                        train * data_config.above_train_limit.get(&ipnet).0 * attack_ratio;
+                    if attack <= attack_train_threshold {
+                        false_negatives += attack;
+                    } else {
+                        false_negatives += attack_train_threshold;
+                        true_positives += attack - attack_train_threshold;
+                    }
+
+                    if data_config.bandwidth_budget.is_some() {
+                        admitted_sources.push((
+                            ipnet,
+                            test.min(test_train_threshold),
+                            attack.min(attack_train_threshold),
+                        ));
+                    }
                 }
             }
 
@@ -601,7 +865,53 @@ async fn evaluate_configuration(
                 "Received an allowlist entry for {ipnet} but no traffic in the train period."
             ),
         }
+
+        let after = (true_positives, true_negatives, false_positives, false_negatives);
+        let totals = family_totals.select_mut(&ipnet);
+        totals.true_positives += after.0 - before.0;
+        totals.true_negatives += after.1 - before.1;
+        totals.false_positives += after.2 - before.2;
+        totals.false_negatives += after.3 - before.3;
+    }
+
+    // Second reconciliation pass: models a finite mitigation/link capacity on top of the
+    // per-source admission decisions above. Once the summed admitted traffic crosses
+    // `start_check_fraction` of the budget, every source's admitted volume is scaled down by the
+    // same factor `f = budget / admitted_sum`, and the shed excess is reclassified into the
+    // appropriate false-positive (dropped legitimate traffic) / true-positive (dropped attack
+    // traffic) buckets, proportionally to how much of that source's admitted volume was attack
+    // traffic.
+    if let Some(bandwidth_budget) = data_config.bandwidth_budget {
+        let admitted_sum: f64 = admitted_sources
+            .iter()
+            .map(|&(_, test_admitted, attack_admitted)| test_admitted + attack_admitted)
+            .sum();
+        if admitted_sum > bandwidth_budget.start_check_fraction.0 * bandwidth_budget.budget.0 {
+            let factor = (bandwidth_budget.budget.0 / admitted_sum).min(1.0);
+            for (ipnet, test_admitted, attack_admitted) in admitted_sources {
+                let source_admitted = test_admitted + attack_admitted;
+                if source_admitted == 0. {
+                    continue;
+                }
+                let attack_ratio = attack_admitted / source_admitted;
+                let shed = source_admitted * (1. - factor);
+                let shed_attack = shed * attack_ratio;
+                let shed_test = shed - shed_attack;
+
+                true_negatives -= shed_test;
+                false_positives += shed_test;
+                false_negatives -= shed_attack;
+                true_positives += shed_attack;
+
+                let totals = family_totals.select_mut(&ipnet);
+                totals.true_negatives -= shed_test;
+                totals.false_positives += shed_test;
+                totals.false_negatives -= shed_attack;
+                totals.true_positives += shed_attack;
+            }
+        }
     }
+
     // Check that the computation makes sense
     assert!(!total.is_nan());
     assert!(!true_positives.is_nan());
@@ -628,14 +938,16 @@ async fn evaluate_configuration(
         true_negatives,
         false_positives,
         false_negatives,
+        by_family: Some(family_totals),
     })
 }
 
-/// Fetch the allowlist matching the time interval given via `data_config`.
+/// Fetch the allowlist matching the time interval given via `data_config`, partitioned by
+/// address family.
 async fn fetch_allowlist(
-    data_config: DataConfiguration<Arc<AttackerTrafficDistribution>>,
+    data_config: &DataConfiguration<Arc<AttackerTrafficDistribution>>,
     pool: PgPool,
-) -> Result<BTreeMap<IpNetwork, ()>> {
+) -> Result<PerFamily<BTreeMap<IpNetwork, ()>>> {
     // Fields in allowlist table
     // time_start   │ integer
     // train_window │ integer
@@ -676,7 +988,16 @@ async fn fetch_allowlist(
     })?;
 
     match dbresults {
-        Some(dbresults) => Ok(dbresults.into_iter().map(|x| (x, ())).collect()),
+        Some(dbresults) => {
+            let mut allowlist = PerFamily {
+                v4: BTreeMap::new(),
+                v6: BTreeMap::new(),
+            };
+            for net in dbresults {
+                allowlist.select_mut(&net).insert(net, ());
+            }
+            Ok(allowlist)
+        }
         None => {
             #[allow(clippy::type_complexity)]
             static WARN_ONCE: once_cell::sync::Lazy<
@@ -699,19 +1020,24 @@ async fn fetch_allowlist(
                 );
             };
 
-            Ok(BTreeMap::new())
+            Ok(PerFamily {
+                v4: BTreeMap::new(),
+                v6: BTreeMap::new(),
+            })
         }
     }
 }
 
-/// Fetch data from the pre-aggregated `traffic_interval` table.
+/// Fetch data from the pre-aggregated `traffic_interval` table, partitioned by address family:
+/// IPv4 and IPv6 source distributions behave very differently, and callers aggregate each family
+/// to its own prefix length before use.
 async fn fetch_traffic_interval(
     time_start: u32,
     window: u8,
     location: &'static str,
     iprange_dst: IpNetwork,
     pool: PgPool,
-) -> Result<BTreeMap<IpNetwork, f64>> {
+) -> Result<PerFamily<BTreeMap<IpNetwork, f32>>> {
     // time_start   │ integer
     // train_window │ integer
     // location     │ text
@@ -749,113 +1075,303 @@ async fn fetch_traffic_interval(
         )
     })?;
 
-    let traffic: BTreeMap<IpNetwork, f64> =
-        iter::zip(record.iprange_srcs, record.pkts_avgs).collect();
+    // Traffic values are stored as f32: only their magnitude against the low-pass/training
+    // thresholds matters, and the window cache holds this data for the whole grid search.
+    let mut traffic = PerFamily {
+        v4: BTreeMap::new(),
+        v6: BTreeMap::new(),
+    };
+    for (net, pkts) in iter::zip(
+        record.iprange_srcs,
+        record.pkts_avgs.into_iter().map(|pkts| pkts as f32),
+    ) {
+        traffic.select_mut(&net).insert(net, pkts);
+    }
     Ok(traffic)
 }
 
-/// Merge multiple data source into a single iterator while synchronizing them.
-///
-/// The function takes multiple maps, all keyed on a [`IpNetwork`], and returns an iterator the joined data.
-/// If the data is not available in one of the maps, `None` is returned.
-fn giant_merge_join<'a, A, B, C, D>(
-    attack_traffic: &'a BTreeMap<IpNetwork, A>,
-    allowlist: &'a BTreeMap<IpNetwork, B>,
-    train_traffic: &'a BTreeMap<IpNetwork, C>,
-    test_traffic: &'a BTreeMap<IpNetwork, D>,
-) -> impl Iterator<
-    Item = (
-        &'a IpNetwork,
-        (Option<&'a A>, Option<&'a B>, Option<&'a C>, Option<&'a D>),
-    ),
-> {
-    use itertools::Itertools as _;
-
-    // Sanity check the multiple inputs, to ensure that merging them is actually possible correctly
-    // The IpNetwork type can be unequal, if the underlying IP address from which it was created is unequal.
-    // This can lead to a situation there two networks, which should match, are not equal.
-    for net in attack_traffic.keys() {
-        assert_eq!(
-            net.ip(),
-            net.network(),
-            "Attack traffic network is not normalized {net:?}"
-        );
+/// A value that can be combined with another of its own kind, used to fold traffic recorded at
+/// multiple prefixes covering the same address space into one.
+trait Mergeable: Copy {
+    fn merge(self, other: Self) -> Self;
+}
+
+impl Mergeable for f32 {
+    fn merge(self, other: Self) -> Self {
+        self + other
     }
-    for net in allowlist.keys() {
-        assert_eq!(
-            net.ip(),
-            net.network(),
-            "Allowlist network is not normalized {net:?}"
-        );
+}
+
+impl Mergeable for () {
+    fn merge(self, _other: Self) -> Self {}
+}
+
+/// Re-key every entry in `map` to `prefix`, summing values that land on the same truncated key.
+/// Used to collapse a family's sources to its configured aggregation prefix (see
+/// `DataConfiguration::aggregation_prefix`) before `giant_merge_join`, so e.g. IPv6 sources,
+/// which would otherwise sit at their native per-address granularity, don't explode the traffic
+/// maps.
+fn aggregate_to_prefix<T: Mergeable>(map: &BTreeMap<IpNetwork, T>, prefix: u8) -> BTreeMap<IpNetwork, T> {
+    let mut out: BTreeMap<IpNetwork, T> = BTreeMap::new();
+    for (&net, &value) in map {
+        let truncated = IpNetwork::new(net.ip(), prefix).expect("Prefix size never exceeds limit.");
+        let truncated =
+            IpNetwork::new(truncated.network(), prefix).expect("Prefix size never exceeds limit.");
+        out.entry(truncated)
+            .and_modify(|existing| *existing = existing.merge(value))
+            .or_insert(value);
     }
-    for net in train_traffic.keys() {
-        assert_eq!(
-            net.ip(),
-            net.network(),
-            "Train traffic network is not normalized {net:?}"
-        );
+    out
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct PackedPrefix<const N: usize> {
+    addr: [u8; N],
+    pfxlen: u8,
+}
+
+impl<const N: usize> PackedPrefix<N> {
+    fn bit(&self, index: u8) -> usize {
+        let byte = self.addr[(index / 8) as usize];
+        ((byte >> (7 - (index % 8))) & 1) as usize
     }
-    for net in test_traffic.keys() {
-        assert_eq!(
-            net.ip(),
-            net.network(),
-            "Test traffic network is not normalized {net:?}"
-        );
+}
+
+struct TrieNode<T> {
+    value: Option<T>,
+    children: [Option<Box<TrieNode<T>>>; 2],
+}
+
+impl<T> Default for TrieNode<T> {
+    fn default() -> Self {
+        Self {
+            value: None,
+            children: [None, None],
+        }
     }
+}
+
+/// Binary radix trie from address prefixes to a [`Mergeable`] value, supporting longest-prefix
+/// lookup and an `aggregate6`-style aggregation pass.
+struct PrefixTrie<const N: usize, T> {
+    root: TrieNode<T>,
+}
 
-    trait FlattenTuple {
-        type Output;
-        fn into_flattened(self) -> Self::Output;
+impl<const N: usize, T> Default for PrefixTrie<N, T> {
+    fn default() -> Self {
+        Self {
+            root: TrieNode::default(),
+        }
     }
+}
 
-    impl<A, B, C> FlattenTuple for (Option<(Option<A>, Option<B>)>, Option<C>) {
-        type Output = (Option<A>, Option<B>, Option<C>);
+impl<const N: usize, T: Mergeable> PrefixTrie<N, T> {
+    fn insert_or_merge(&mut self, prefix: PackedPrefix<N>, value: T) {
+        let mut node = &mut self.root;
+        for i in 0..prefix.pfxlen {
+            node = node.children[prefix.bit(i)].get_or_insert_with(Default::default);
+        }
+        node.value = Some(match node.value {
+            Some(existing) => existing.merge(value),
+            None => value,
+        });
+    }
 
-        fn into_flattened(self) -> Self::Output {
-            match self {
-                (None, c) => (None, None, c),
-                (Some((a, b)), c) => (a, b, c),
+    /// Collapse sibling prefixes that together cover their parent into a new, merged entry at
+    /// that parent, and fold any prefix already covered by a broader prefix present in the same
+    /// trie into that broader entry, summing values either way. Applied recursively bottom-up,
+    /// so a chain of collapses (e.g. four /26 siblings into one /24) happens in one pass.
+    fn aggregate(&mut self) {
+        Self::aggregate_node(&mut self.root);
+    }
+
+    fn aggregate_node(node: &mut TrieNode<T>) -> Option<T> {
+        let child_values = [
+            node.children[0].as_deref_mut().and_then(Self::aggregate_node),
+            node.children[1].as_deref_mut().and_then(Self::aggregate_node),
+        ];
+        match (node.value, child_values) {
+            (Some(existing), _) => {
+                node.value = Some(
+                    child_values
+                        .into_iter()
+                        .flatten()
+                        .fold(existing, Mergeable::merge),
+                );
+                Self::clear_descendant_values(node);
             }
+            (None, [Some(a), Some(b)]) => {
+                node.value = Some(a.merge(b));
+                Self::clear_descendant_values(node);
+            }
+            (None, _) => {}
         }
+        node.value
     }
 
-    impl<A, B, C, D> FlattenTuple for (Option<(Option<A>, Option<B>, Option<C>)>, Option<D>) {
-        type Output = (Option<A>, Option<B>, Option<C>, Option<D>);
+    fn clear_descendant_values(node: &mut TrieNode<T>) {
+        for child in node.children.iter_mut().flatten() {
+            child.value = None;
+            Self::clear_descendant_values(child);
+        }
+    }
 
-        fn into_flattened(self) -> Self::Output {
-            match self {
-                (None, d) => (None, None, None, d),
-                (Some((a, b, c)), d) => (a, b, c, d),
+    fn longest_match(&self, addr: &[u8; N]) -> Option<T> {
+        let mut node = &self.root;
+        let mut best = node.value;
+        for i in 0..(N as u8 * 8) {
+            let byte = addr[(i / 8) as usize];
+            let bit = ((byte >> (7 - (i % 8))) & 1) as usize;
+            match &node.children[bit] {
+                Some(child) => {
+                    node = child;
+                    if node.value.is_some() {
+                        best = node.value;
+                    }
+                }
+                None => break,
             }
         }
+        best
     }
 
-    fn merge_by<Key, ValueLeft, ValueRight>(
-        (left_key, _): &(Key, ValueLeft),
-        (right_key, _): &(Key, ValueRight),
-    ) -> Ordering
-    where
-        Key: Ord,
-    {
-        left_key.cmp(right_key)
+    /// Walk every remaining (non-overlapping, fully aggregated) entry back into an `IpNetwork`
+    /// keyed map, via `family` to turn this trie's raw address bytes and prefix length back into
+    /// the right `IpNetwork` variant.
+    fn into_map(self, family: fn([u8; N], u8) -> IpNetwork) -> BTreeMap<IpNetwork, T> {
+        let mut out = BTreeMap::new();
+        Self::collect(&self.root, [0; N], 0, family, &mut out);
+        out
     }
 
-    fn merge_item<Key, ValueLeft, ValueRight>(
-        item: itertools::EitherOrBoth<(Key, ValueLeft), (Key, ValueRight)>,
-    ) -> (Key, (Option<ValueLeft>, Option<ValueRight>)) {
-        match item {
-            itertools::EitherOrBoth::Both((kl, vl), (_, vr)) => (kl, (Some(vl), Some(vr))),
-            itertools::EitherOrBoth::Left((kl, vl)) => (kl, (Some(vl), None)),
-            itertools::EitherOrBoth::Right((kr, vr)) => (kr, (None, Some(vr))),
+    fn collect(
+        node: &TrieNode<T>,
+        addr: [u8; N],
+        depth: u8,
+        family: fn([u8; N], u8) -> IpNetwork,
+        out: &mut BTreeMap<IpNetwork, T>,
+    ) {
+        if let Some(value) = node.value {
+            // Aggregation always clears a node's descendants once it carries a value, so there
+            // is nothing more specific left to collect below this point.
+            out.insert(family(addr, depth), value);
+            return;
+        }
+        for (bit, child) in node.children.iter().enumerate() {
+            let Some(child) = child else { continue };
+            let mut addr = addr;
+            let byte = (depth / 8) as usize;
+            let mask = 1u8 << (7 - (depth % 8));
+            if bit == 1 {
+                addr[byte] |= mask;
+            } else {
+                addr[byte] &= !mask;
+            }
+            Self::collect(child, addr, depth + 1, family, out);
         }
     }
+}
 
-    itertools::merge_join_by(attack_traffic, allowlist, merge_by)
-        .map(merge_item)
-        .merge_join_by(train_traffic, merge_by)
-        .map(merge_item)
-        .map(|(k, v)| (k, v.into_flattened()))
-        .merge_join_by(test_traffic, merge_by)
-        .map(merge_item)
-        .map(|(k, v)| (k, v.into_flattened()))
+/// Dual-family (IPv4/IPv6) [`PrefixTrie`], aggregated on construction.
+struct CoverageMap<T: Mergeable> {
+    v4: PrefixTrie<4, T>,
+    v6: PrefixTrie<16, T>,
+}
+
+impl<T: Mergeable> CoverageMap<T> {
+    fn from_map(map: &BTreeMap<IpNetwork, T>) -> Self {
+        let mut v4 = PrefixTrie::default();
+        let mut v6 = PrefixTrie::default();
+        for (&net, &value) in map {
+            match net {
+                IpNetwork::V4(net) => v4.insert_or_merge(
+                    PackedPrefix {
+                        addr: net.network().octets(),
+                        pfxlen: net.prefix(),
+                    },
+                    value,
+                ),
+                IpNetwork::V6(net) => v6.insert_or_merge(
+                    PackedPrefix {
+                        addr: net.network().octets(),
+                        pfxlen: net.prefix(),
+                    },
+                    value,
+                ),
+            }
+        }
+        v4.aggregate();
+        v6.aggregate();
+        Self { v4, v6 }
+    }
+
+    /// The most-specific aggregated entry covering `addr`, if any.
+    fn longest_match(&self, addr: IpAddr) -> Option<T> {
+        match addr {
+            IpAddr::V4(addr) => self.v4.longest_match(&addr.octets()),
+            IpAddr::V6(addr) => self.v6.longest_match(&addr.octets()),
+        }
+    }
+
+    fn into_map(self) -> BTreeMap<IpNetwork, T> {
+        let mut out = self.v4.into_map(|addr, pfxlen| {
+            IpNetwork::V4(
+                Ipv4Network::new(Ipv4Addr::from(addr), pfxlen).expect("valid IPv4 prefix length"),
+            )
+        });
+        out.extend(self.v6.into_map(|addr, pfxlen| {
+            IpNetwork::V6(
+                Ipv6Network::new(Ipv6Addr::from(addr), pfxlen).expect("valid IPv6 prefix length"),
+            )
+        }));
+        out
+    }
+}
+
+/// Merge multiple data sources into a single iterator while synchronizing them.
+///
+/// `attack_traffic` and `test_traffic` are joined by exact key equality, since both are recorded
+/// at the same per-source granularity. `allowlist` and `train_traffic` are instead looked up by
+/// longest-prefix match: an allowlist entry, or a training window, may cover a source at a
+/// coarser granularity than the source itself, and exact-key equality would otherwise silently
+/// treat such a source as absent from both.
+///
+/// Before joining, every input map goes through an `aggregate6`-style pre-pass (see
+/// [`PrefixTrie::aggregate`]): sibling prefixes that together cover their parent are collapsed
+/// into it, and any prefix already covered by a broader prefix present in the same map is folded
+/// into that broader entry, summing values either way.
+fn giant_merge_join<A, C, D>(
+    attack_traffic: &BTreeMap<IpNetwork, A>,
+    allowlist: &BTreeMap<IpNetwork, ()>,
+    train_traffic: &BTreeMap<IpNetwork, C>,
+    test_traffic: &BTreeMap<IpNetwork, D>,
+) -> impl Iterator<Item = (IpNetwork, (Option<A>, Option<()>, Option<C>, Option<D>))>
+where
+    A: Mergeable,
+    C: Mergeable,
+    D: Mergeable,
+{
+    let attack_traffic = CoverageMap::from_map(attack_traffic).into_map();
+    let test_traffic = CoverageMap::from_map(test_traffic).into_map();
+    let allowlist = CoverageMap::from_map(allowlist);
+    let train_traffic = CoverageMap::from_map(train_traffic);
+
+    itertools::merge_join_by(attack_traffic, test_traffic, |(l, _), (r, _)| l.cmp(r))
+        .map(|item| match item {
+            itertools::EitherOrBoth::Both((net, a), (_, d)) => (net, (Some(a), Some(d))),
+            itertools::EitherOrBoth::Left((net, a)) => (net, (Some(a), None)),
+            itertools::EitherOrBoth::Right((net, d)) => (net, (None, Some(d))),
+        })
+        .map(move |(net, (a, d))| {
+            let addr = net.ip();
+            (
+                net,
+                (
+                    a,
+                    allowlist.longest_match(addr),
+                    train_traffic.longest_match(addr),
+                    d,
+                ),
+            )
+        })
 }
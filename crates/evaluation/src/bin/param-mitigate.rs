@@ -0,0 +1,185 @@
+//! Turn one grid-search-winning configuration into deployable mitigation artifacts.
+//!
+//! Given the parameters of a `DataConfiguration` (identifying a row of the `allowlist` table)
+//! and the same weighted attacker-IP file used by `param-grid-search`, this writes:
+//!
+//! 1. A FlowSpec-style rule set in the text form an exabgp `announce` process consumes, rate
+//!    limiting (or dropping, with `--rate-limit-pps 0`) every non-allowlisted attacker source
+//!    toward `--iprange-dst`.
+//! 2. A plain newline-delimited blocklist of the same non-allowlisted attacker prefixes.
+//!
+//! This closes the loop between the simulation and an actual anycast deployment: an operator
+//! takes the parameter combination the grid search found best and pushes it to a router.
+
+#![deny(unused_import_braces, unused_qualifications)]
+
+use color_eyre::eyre::{Context as _, Result};
+use evaluation::allowlist::AllowlistSet;
+use evaluation::Location;
+use sqlx::postgres::PgConnectOptions;
+use sqlx::types::ipnetwork::IpNetwork;
+use sqlx::{ConnectOptions as _, PgPool};
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::str::FromStr as _;
+use std::time::Duration;
+
+/// Prefix length non-allowlisted sources are aggregated to before being emitted as one
+/// mitigation entry, matching the aggregation used by `param-grid-search`.
+const SOURCE_AGGREGATION_PREFIX_V4: u8 = 24;
+const SOURCE_AGGREGATION_PREFIX_V6: u8 = 48;
+
+#[derive(Debug, clap::Parser)]
+struct CliArgs {
+    /// JSON file with a weighted list of attacker IP addresses, same format as `param-grid-search`
+    #[clap(long = "attacker-ips")]
+    attacker_ips_file: PathBuf,
+    #[clap(long = "location")]
+    location: String,
+    #[clap(long = "iprange-dst")]
+    iprange_dst: IpNetwork,
+    #[clap(long = "window-start")]
+    window_start: u32,
+    #[clap(long = "train-length")]
+    train_length: u8,
+    #[clap(long = "min-active")]
+    min_active: u8,
+    #[clap(long = "min-pkts-avg")]
+    min_pkts_avg: u32,
+    /// Rate, in packets/sec, non-allowlisted sources are limited to. `0` emits a hard drop instead.
+    #[clap(long = "rate-limit-pps")]
+    rate_limit_pps: u64,
+    #[clap(long = "flowspec-out")]
+    flowspec_out: PathBuf,
+    #[clap(long = "blocklist-out")]
+    blocklist_out: PathBuf,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    color_eyre::install()?;
+    env_logger::init();
+    let args: CliArgs = clap::Parser::parse();
+
+    let mut pgoptions =
+        PgConnectOptions::from_str("postgres:///cctld")?.application_name("evaluation");
+    pgoptions
+        .log_statements(log::LevelFilter::Debug)
+        .log_slow_statements(log::LevelFilter::Info, Duration::new(60, 0));
+    let pool = sqlx::postgres::PgPoolOptions::new()
+        .max_connections(1)
+        .acquire_timeout(Duration::new(60, 0))
+        .connect_with(pgoptions)
+        .await?;
+
+    let location = Location::from_str(&args.location)?;
+    let allowlist = fetch_allowlist(
+        &pool,
+        args.window_start,
+        args.train_length,
+        args.min_active,
+        args.min_pkts_avg,
+        location,
+        args.iprange_dst,
+    )
+    .await
+    .context("Failed to fetch allowlist")?;
+    let allowlist = AllowlistSet::from_networks(allowlist);
+
+    let attacker_ips: BTreeMap<IpAddr, f64> = {
+        let data = std::fs::read_to_string(&args.attacker_ips_file)?;
+        serde_json::from_str(&data)?
+    };
+
+    // Aggregate the non-allowlisted attacker sources to one mitigation entry per prefix,
+    // exactly as `param-grid-search` aggregates attacker sources before weighting them.
+    let mut offenders: Vec<IpNetwork> = attacker_ips
+        .keys()
+        .filter(|&&ip| !allowlist.contains(ip))
+        .map(|&ip| {
+            let prefix = match ip {
+                IpAddr::V4(_) => SOURCE_AGGREGATION_PREFIX_V4,
+                IpAddr::V6(_) => SOURCE_AGGREGATION_PREFIX_V6,
+            };
+            let network = IpNetwork::new(ip, prefix).expect("Prefix size never exceeds limit.");
+            IpNetwork::new(network.network(), prefix).expect("Prefix size never exceeds limit.")
+        })
+        .collect();
+    offenders.sort();
+    offenders.dedup();
+
+    std::fs::write(
+        &args.flowspec_out,
+        render_flowspec(&offenders, args.iprange_dst, args.rate_limit_pps),
+    )?;
+    std::fs::write(
+        &args.blocklist_out,
+        offenders
+            .iter()
+            .map(|network| format!("{network}\n"))
+            .collect::<String>(),
+    )?;
+
+    Ok(())
+}
+
+/// Render one exabgp `flow route` stanza per offending source, matching `destination
+/// iprange_dst` and `source offender`, either rate-limiting or discarding the traffic.
+fn render_flowspec(offenders: &[IpNetwork], iprange_dst: IpNetwork, rate_limit_pps: u64) -> String {
+    let mut out = String::new();
+    for offender in offenders {
+        out.push_str("flow route {\n");
+        out.push_str("    match {\n");
+        out.push_str(&format!("        destination {iprange_dst};\n"));
+        out.push_str(&format!("        source {offender};\n"));
+        out.push_str("    }\n");
+        out.push_str("    then {\n");
+        if rate_limit_pps == 0 {
+            out.push_str("        discard;\n");
+        } else {
+            out.push_str(&format!("        rate-limit {rate_limit_pps};\n"));
+        }
+        out.push_str("    }\n");
+        out.push_str("}\n");
+    }
+    out
+}
+
+/// Fetch the allowlisted prefixes matching one configuration.
+async fn fetch_allowlist(
+    pool: &PgPool,
+    window_start: u32,
+    train_length: u8,
+    min_active: u8,
+    min_pkts_avg: u32,
+    location: Location,
+    iprange_dst: IpNetwork,
+) -> Result<Vec<IpNetwork>> {
+    let dbresults = sqlx::query_scalar!(
+        r#"SELECT array_agg as "array_agg!" FROM allowlist
+        WHERE time_start = $1
+        AND train_window = $2
+        AND active_min = $3
+        AND pkts_min = $4
+        AND location = $5
+        AND iprange_dst = $6"#,
+        window_start as i32,
+        train_length as i32,
+        min_active as i32,
+        min_pkts_avg as i32,
+        <&'static str>::from(location),
+        iprange_dst,
+    )
+    .fetch_optional(pool)
+    .await
+    .with_context(|| {
+        format!(
+            "Allowlist for time_start {window_start}, train_window {train_length}, active_min \
+             {min_active}, pkts_min {min_pkts_avg}, location {location}, iprange_dst \
+             {iprange_dst}"
+        )
+    })?;
+
+    Ok(dbresults.unwrap_or_default())
+}
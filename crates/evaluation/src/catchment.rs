@@ -0,0 +1,125 @@
+//! Longest-prefix-match catchment table.
+//!
+//! Maps an attacker network to the index of the catchment group (in the
+//! `catchment` file) whose prefixes cover it, by walking a binary radix trie
+//! instead of comparing only the first/last network of each group. Covers
+//! both address families, since catchment data is recorded for IPv4 and
+//! IPv6 sources alike.
+
+use std::net::IpAddr;
+
+use ipnetwork::IpNetwork;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct PackedPrefix<const N: usize> {
+    addr: [u8; N],
+    pfxlen: u8,
+}
+
+impl<const N: usize> PackedPrefix<N> {
+    fn bit(&self, index: u8) -> usize {
+        let byte = self.addr[(index / 8) as usize];
+        ((byte >> (7 - (index % 8))) & 1) as usize
+    }
+}
+
+#[derive(Debug, Default)]
+struct TrieNode {
+    /// Index of the catchment group claiming this node, if a prefix ends here.
+    /// The deepest (most specific) node wins on lookup.
+    group_idx: Option<usize>,
+    children: [Option<Box<TrieNode>>; 2],
+}
+
+#[derive(Debug, Default)]
+struct PrefixTrie<const N: usize> {
+    root: TrieNode,
+}
+
+impl<const N: usize> PrefixTrie<N> {
+    fn insert(&mut self, prefix: PackedPrefix<N>, idx: usize) {
+        let mut node = &mut self.root;
+        for i in 0..prefix.pfxlen {
+            node = node.children[prefix.bit(i)].get_or_insert_with(Default::default);
+        }
+        node.group_idx = Some(idx);
+    }
+
+    fn longest_match(&self, addr: &[u8; N]) -> Option<usize> {
+        let mut node = &self.root;
+        let mut best = node.group_idx;
+        for i in 0..(N as u8 * 8) {
+            let byte = addr[(i / 8) as usize];
+            let bit = ((byte >> (7 - (i % 8))) & 1) as usize;
+            match &node.children[bit] {
+                Some(child) => {
+                    node = child;
+                    if node.group_idx.is_some() {
+                        best = node.group_idx;
+                    }
+                }
+                None => break,
+            }
+        }
+        best
+    }
+}
+
+/// Longest-prefix-match table from IPv4/IPv6 prefixes to a catchment group index.
+#[derive(Debug, Default)]
+pub struct CatchmentTable {
+    v4: PrefixTrie<4>,
+    v6: PrefixTrie<16>,
+}
+
+impl CatchmentTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Insert `network`, pointing at catchment group `idx`. Overlapping/nested
+    /// prefixes are handled naturally: `longest_match` always prefers the
+    /// deepest (most specific) matching node.
+    pub fn insert(&mut self, network: IpNetwork, idx: usize) {
+        match network {
+            IpNetwork::V4(network) => self.v4.insert(
+                PackedPrefix {
+                    addr: network.network().octets(),
+                    pfxlen: network.prefix(),
+                },
+                idx,
+            ),
+            IpNetwork::V6(network) => self.v6.insert(
+                PackedPrefix {
+                    addr: network.network().octets(),
+                    pfxlen: network.prefix(),
+                },
+                idx,
+            ),
+        }
+    }
+
+    /// Return the catchment group index whose prefix most specifically covers `addr`.
+    pub fn longest_match(&self, addr: IpAddr) -> Option<usize> {
+        match addr {
+            IpAddr::V4(addr) => self.v4.longest_match(&addr.octets()),
+            IpAddr::V6(addr) => self.v6.longest_match(&addr.octets()),
+        }
+    }
+}
+
+#[test]
+fn test_catchment_table_prefers_more_specific() {
+    let mut table = CatchmentTable::new();
+    table.insert("0.0.0.0/0".parse().unwrap(), 0);
+    table.insert("198.51.100.0/24".parse().unwrap(), 1);
+    table.insert("2001:db8::/32".parse().unwrap(), 2);
+
+    assert_eq!(
+        table.longest_match("198.51.100.53".parse().unwrap()),
+        Some(1)
+    );
+    assert_eq!(table.longest_match("203.0.113.1".parse().unwrap()), Some(0));
+    assert_eq!(table.longest_match("2001:db8::53".parse().unwrap()), Some(2));
+    assert_eq!(table.longest_match("2001:db9::53".parse().unwrap()), None);
+}
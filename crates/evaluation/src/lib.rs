@@ -1,5 +1,11 @@
 use ipnetwork::IpNetwork;
 
+pub mod allowlist;
+pub mod anomaly;
+pub mod catchment;
+pub mod mitigation;
+pub mod mrt;
+
 #[allow(non_camel_case_types)]
 #[derive(
     Copy,
@@ -36,6 +42,70 @@ impl Location {
     }
 }
 
+/// Burst/refill parameters for the token-bucket low-pass mode.
+///
+/// A not-allowlisted source may send up to `capacity` packets as an
+/// instantaneous burst; beyond that, packets pass at `refill_rate`
+/// packets/second, i.e. over a window of `D` seconds at most
+/// `capacity + refill_rate * D` packets pass through.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, serde::Serialize, serde::Deserialize)]
+pub struct TokenBucket {
+    pub capacity: u32,
+    pub refill_rate: u32,
+}
+
+/// Confidence level controlling the per-source Mahalanobis-distance anomaly cutoff; see
+/// [`crate::anomaly::MahalanobisModel`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, serde::Serialize, serde::Deserialize)]
+pub struct MahalanobisConfig {
+    pub confidence_level: ordered_float::OrderedFloat<f64>,
+}
+
+/// Total admitted-traffic capacity modeling a finite mitigation/link budget, applied as a second
+/// reconciliation pass after per-source admission decisions; see the "downgrade" pass in
+/// `param-grid-search`'s `evaluate_configuration`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, serde::Serialize, serde::Deserialize)]
+pub struct BandwidthBudget {
+    /// Total admitted traffic the link/mitigation can carry, in the same units as traffic values.
+    pub budget: ordered_float::OrderedFloat<f64>,
+    /// Fraction of `budget` the summed admitted traffic must cross before the downgrade pass
+    /// kicks in, so that loads well under capacity are left untouched.
+    pub start_check_fraction: ordered_float::OrderedFloat<f64>,
+}
+
+/// A value that differs between IPv4 and IPv6, e.g. a per-family threshold, aggregation prefix
+/// length, or partitioned traffic map.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, serde::Serialize, serde::Deserialize)]
+pub struct PerFamily<T> {
+    pub v4: T,
+    pub v6: T,
+}
+
+impl<T> PerFamily<T> {
+    /// The value for `network`'s address family.
+    pub fn select(&self, network: &IpNetwork) -> &T {
+        match network {
+            IpNetwork::V4(_) => &self.v4,
+            IpNetwork::V6(_) => &self.v6,
+        }
+    }
+
+    /// A mutable reference to the value for `network`'s address family.
+    pub fn select_mut(&mut self, network: &IpNetwork) -> &mut T {
+        match network {
+            IpNetwork::V4(_) => &mut self.v4,
+            IpNetwork::V6(_) => &mut self.v6,
+        }
+    }
+}
+
+impl<T: Copy> PerFamily<T> {
+    /// The value for `network`'s address family, copied out.
+    pub fn get(&self, network: &IpNetwork) -> T {
+        *self.select(network)
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, serde::Serialize, serde::Deserialize)]
 pub struct DataConfiguration<A> {
     pub location: Location,
@@ -45,12 +115,54 @@ pub struct DataConfiguration<A> {
     pub test_length: u8,
     pub min_active: u8,
     pub min_pkts_avg: u32,
-    pub low_pass: u32,
-    pub above_train_limit: ordered_float::OrderedFloat<f64>,
+    /// Pass-through cap for not-allowlisted sources, separate per address family since IPv4 and
+    /// IPv6 source distributions behave very differently.
+    pub low_pass: PerFamily<u32>,
+    /// When set, not-allowlisted sources are shaped by a token bucket instead
+    /// of the flat `low_pass` cap.
+    pub token_bucket: Option<TokenBucket>,
+    /// When set, allowlisted sources are screened by a per-source Mahalanobis-distance anomaly
+    /// score (see [`crate::anomaly`]) instead of the flat `train * above_train_limit` ratio.
+    pub anomaly: Option<MahalanobisConfig>,
+    pub above_train_limit: PerFamily<ordered_float::OrderedFloat<f64>>,
+    /// Prefix length each family's source keys are aggregated to before `giant_merge_join`'s
+    /// join, e.g. `/24` for IPv4 vs `/48` for IPv6 — aggregating IPv6 sources at their native
+    /// per-address granularity would explode the allowlist.
+    pub aggregation_prefix: PerFamily<u8>,
+    /// When set, a finite total admitted-traffic capacity enforced after the per-source
+    /// admission decisions above, shedding the excess proportionally across sources.
+    pub bandwidth_budget: Option<BandwidthBudget>,
     #[serde(skip, default, bound = "")]
     pub attacker: A,
 }
 
+/// TP/TN/FP/FN totals for a single address family; see [`EvaluationResults::by_family`].
+#[derive(Clone, Copy, Debug, Default, serde::Serialize, serde::Deserialize)]
+pub struct FamilyTotals {
+    pub true_positives: f64,
+    pub true_negatives: f64,
+    pub false_positives: f64,
+    pub false_negatives: f64,
+}
+
+impl std::ops::Add for FamilyTotals {
+    type Output = Self;
+
+    fn add(mut self, rhs: Self) -> Self {
+        self += rhs;
+        self
+    }
+}
+
+impl std::ops::AddAssign for FamilyTotals {
+    fn add_assign(&mut self, rhs: Self) {
+        self.true_positives += rhs.true_positives;
+        self.true_negatives += rhs.true_negatives;
+        self.false_positives += rhs.false_positives;
+        self.false_negatives += rhs.false_negatives;
+    }
+}
+
 #[derive(Clone, Copy, Debug, serde::Serialize, serde::Deserialize)]
 pub struct EvaluationResults {
     pub total: f64,
@@ -58,6 +170,10 @@ pub struct EvaluationResults {
     pub true_negatives: f64,
     pub false_positives: f64,
     pub false_negatives: f64,
+    /// IPv4/IPv6 breakdown of the totals above, so IPv6 mitigation quality can be assessed
+    /// separately from IPv4. `None` for results computed before this breakdown existed.
+    #[serde(default)]
+    pub by_family: Option<PerFamily<FamilyTotals>>,
 }
 
 impl EvaluationResults {
@@ -105,11 +221,7 @@ impl std::ops::Add for EvaluationResults {
     type Output = Self;
 
     fn add(mut self, rhs: Self) -> Self {
-        self.total += rhs.total;
-        self.true_positives += rhs.true_positives;
-        self.true_negatives += rhs.true_negatives;
-        self.false_positives += rhs.false_positives;
-        self.false_negatives += rhs.false_negatives;
+        self += rhs;
         self
     }
 }
@@ -121,6 +233,13 @@ impl std::ops::AddAssign for EvaluationResults {
         self.true_negatives += rhs.true_negatives;
         self.false_positives += rhs.false_positives;
         self.false_negatives += rhs.false_negatives;
+        self.by_family = match (self.by_family, rhs.by_family) {
+            (Some(a), Some(b)) => Some(PerFamily {
+                v4: a.v4 + b.v4,
+                v6: a.v6 + b.v6,
+            }),
+            (a, b) => a.or(b),
+        };
     }
 }
 
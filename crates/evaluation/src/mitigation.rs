@@ -0,0 +1,174 @@
+//! Render a set of flagged attacker source networks into operator-consumable mitigation
+//! artifacts.
+//!
+//! Detection tooling (`param-grid-search`'s `false_negatives`, `param-mitigate`'s
+//! non-allowlisted offenders, ...) ends up with a flat set of attacker source networks; this
+//! module owns turning that set into something an operator can actually push to a router or
+//! firewall. [`summarize_for_mitigation`] collapses the set to its minimal CIDR cover with the
+//! same sibling-merge fixed point `netflow::aggregate::prefix_summary` uses, and stamps every
+//! surviving entry with an expiry so a feed consumer can age entries out; [`render_cidr_list`],
+//! [`render_nftables_set`] and [`render_json_feed`] then format that summarized set for a
+//! plain blocklist, an `nftables` named set, or a JSON feed respectively.
+
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+use ipnetwork::{IpNetwork, Ipv4Network, Ipv6Network};
+
+/// One summarized mitigation entry: a minimal-cover network, plus the unix timestamp (seconds)
+/// after which it should be dropped from an active blocklist.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct MitigationEntry {
+    pub network: IpNetwork,
+    pub expires_at: u64,
+}
+
+/// Collapse `networks` to their minimal CIDR cover and stamp every surviving entry with
+/// `now + ttl_secs` as its expiry.
+pub fn summarize_for_mitigation(
+    networks: impl IntoIterator<Item = IpNetwork>,
+    now: u64,
+    ttl_secs: u64,
+) -> Vec<MitigationEntry> {
+    let expires_at = now + ttl_secs;
+
+    let mut v4 = Vec::new();
+    let mut v6 = Vec::new();
+    for network in networks {
+        match network {
+            IpNetwork::V4(network) => {
+                v4.push((u32::from_be_bytes(network.network().octets()), network.prefix()))
+            }
+            IpNetwork::V6(network) => {
+                v6.push((u128::from_be_bytes(network.network().octets()), network.prefix()))
+            }
+        }
+    }
+
+    let v4 = merge_prefixes(v4, mask_v4).into_iter().map(|(network, prefix_len)| MitigationEntry {
+        network: IpNetwork::V4(
+            Ipv4Network::new(Ipv4Addr::from(network), prefix_len)
+                .expect("prefix_len never exceeds 32"),
+        ),
+        expires_at,
+    });
+    let v6 = merge_prefixes(v6, mask_v6).into_iter().map(|(network, prefix_len)| MitigationEntry {
+        network: IpNetwork::V6(
+            Ipv6Network::new(Ipv6Addr::from(network), prefix_len)
+                .expect("prefix_len never exceeds 128"),
+        ),
+        expires_at,
+    });
+    v4.chain(v6).collect()
+}
+
+/// Classic CIDR-merge fixed point: drop prefixes already covered by a preceding, equal-or-shorter
+/// one, then merge sibling pairs (two equal-length prefixes sharing the same one-shorter parent)
+/// into that parent, repeating until a pass changes nothing. Mirrors
+/// `netflow::aggregate::prefix_summary::merge_prefixes`, minus the packet-count column this
+/// module has no use for.
+fn merge_prefixes<Addr>(
+    mut prefixes: Vec<(Addr, u8)>,
+    mask: impl Fn(Addr, u8) -> Addr,
+) -> Vec<(Addr, u8)>
+where
+    Addr: Copy + Ord,
+{
+    loop {
+        prefixes.sort();
+        prefixes.dedup();
+
+        let mut deduped: Vec<(Addr, u8)> = Vec::with_capacity(prefixes.len());
+        for (network, prefix_len) in prefixes {
+            if let Some(&(parent_network, parent_len)) = deduped.last() {
+                if parent_len <= prefix_len && mask(network, parent_len) == parent_network {
+                    continue;
+                }
+            }
+            deduped.push((network, prefix_len));
+        }
+
+        let mut merged: Vec<(Addr, u8)> = Vec::with_capacity(deduped.len());
+        let mut changed = false;
+        let mut iter = deduped.into_iter().peekable();
+        while let Some((network, prefix_len)) = iter.next() {
+            if prefix_len > 0 {
+                if let Some(&(next_network, next_len)) = iter.peek() {
+                    if next_len == prefix_len
+                        && mask(network, prefix_len - 1) == mask(next_network, prefix_len - 1)
+                    {
+                        merged.push((mask(network, prefix_len - 1), prefix_len - 1));
+                        iter.next();
+                        changed = true;
+                        continue;
+                    }
+                }
+            }
+            merged.push((network, prefix_len));
+        }
+
+        prefixes = merged;
+        if !changed {
+            return prefixes;
+        }
+    }
+}
+
+fn mask_v4(addr: u32, prefix_len: u8) -> u32 {
+    if prefix_len == 0 {
+        return 0;
+    }
+    assert!(prefix_len <= 32, "CIDR for IPv4 must be <= 32");
+    let mask = !((1u32 << (32 - prefix_len)) - 1);
+    addr & mask
+}
+
+fn mask_v6(addr: u128, prefix_len: u8) -> u128 {
+    if prefix_len == 0 {
+        return 0;
+    }
+    assert!(prefix_len <= 128, "CIDR for IPv6 must be <= 128");
+    let mask = !((1u128 << (128 - prefix_len)) - 1);
+    addr & mask
+}
+
+/// Render a plain newline-delimited CIDR list, one network per line.
+pub fn render_cidr_list(entries: &[MitigationEntry]) -> String {
+    entries.iter().map(|entry| format!("{}\n", entry.network)).collect()
+}
+
+/// Render an `nftables` named-set definition covering every entry, ready to feed to `nft -f`.
+pub fn render_nftables_set(entries: &[MitigationEntry], set_name: &str) -> String {
+    let elements =
+        entries.iter().map(|entry| entry.network.to_string()).collect::<Vec<_>>().join(", ");
+    format!("add element inet filter {set_name} {{ {elements} }}\n")
+}
+
+/// Render the full feed (network plus expiry) as JSON, suitable for pushing to an external API.
+pub fn render_json_feed(entries: &[MitigationEntry]) -> serde_json::Result<String> {
+    serde_json::to_string(entries)
+}
+
+#[test]
+fn test_summarize_merges_siblings_and_drops_contained() {
+    let networks = [
+        "198.51.100.0/25".parse().unwrap(),
+        "198.51.100.128/25".parse().unwrap(),
+        "198.51.100.5/32".parse().unwrap(),
+    ];
+    let entries = summarize_for_mitigation(networks, 1_000, 60);
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].network, "198.51.100.0/24".parse::<IpNetwork>().unwrap());
+    assert_eq!(entries[0].expires_at, 1_060);
+}
+
+#[test]
+fn test_render_formats() {
+    let entries = summarize_for_mitigation(["198.51.100.0/24".parse().unwrap()], 0, 60);
+    assert_eq!(render_cidr_list(&entries), "198.51.100.0/24\n");
+    assert_eq!(
+        render_nftables_set(&entries, "blocklist"),
+        "add element inet filter blocklist { 198.51.100.0/24 }\n"
+    );
+    let json = render_json_feed(&entries).unwrap();
+    assert_eq!(json, r#"[{"network":"198.51.100.0/24","expires_at":60}]"#);
+}
@@ -0,0 +1,357 @@
+//! Generate BGP FlowSpec (RFC 5575) mitigation rules from detected attack aggregates.
+//!
+//! The types here only cover the NLRI component set needed to describe a
+//! drop/rate-limit rule for a single attacker source talking to a protected
+//! destination: destination/source prefix, IP protocol, and destination/source
+//! port. They serialize to the wire encoding so the result can be shipped to an
+//! exabgp/gobgp style controller, but this module does not speak BGP itself.
+
+use std::collections::BTreeMap;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use super::Proto;
+
+/// A single numeric-match entry, used for the protocol and port components.
+///
+/// `op` is the RFC 5575 operator byte: bit 0x80 marks the end of the list,
+/// bit 0x40 is the AND/OR bit (unset means OR), bits 0x30 encode the value
+/// length (`0b00` = 1 byte, `0b01` = 2 bytes, `0b10` = 4 bytes), and the low
+/// bits (0x07) carry the comparison (we only ever emit "equals", 0x01).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct NumericMatch {
+    pub op: u8,
+    pub value: u32,
+}
+
+impl NumericMatch {
+    const END_OF_LIST: u8 = 0x80;
+    const LEN_SHIFT: u8 = 4;
+    const OP_EQ: u8 = 0x01;
+
+    /// Build the single, list-terminating "value == x" match used by this crate.
+    fn equals(value: u32) -> Self {
+        let len_bits = if value <= u8::MAX as u32 {
+            0b00
+        } else if value <= u16::MAX as u32 {
+            0b01
+        } else {
+            0b10
+        } << Self::LEN_SHIFT;
+        Self {
+            op: Self::END_OF_LIST | len_bits | Self::OP_EQ,
+            value,
+        }
+    }
+
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.push(self.op);
+        match (self.op >> Self::LEN_SHIFT) & 0b11 {
+            0b00 => out.push(self.value as u8),
+            0b01 => out.extend_from_slice(&(self.value as u16).to_be_bytes()),
+            _ => out.extend_from_slice(&self.value.to_be_bytes()),
+        }
+    }
+}
+
+/// A prefix component (type 1 destination-prefix / type 2 source-prefix), encoded
+/// as a prefix-length byte followed by the minimal number of address bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum FlowSpecPrefix {
+    V4 { addr: Ipv4Addr, pfxlen: u8 },
+    V6 { addr: Ipv6Addr, pfxlen: u8 },
+}
+
+impl FlowSpecPrefix {
+    pub fn host(addr: IpAddr) -> Self {
+        match addr {
+            IpAddr::V4(addr) => Self::V4 { addr, pfxlen: 32 },
+            IpAddr::V6(addr) => Self::V6 { addr, pfxlen: 128 },
+        }
+    }
+
+    fn encode(&self, out: &mut Vec<u8>) {
+        let (octets, pfxlen): (&[u8], u8) = match self {
+            Self::V4 { addr, pfxlen } => (&addr.octets(), *pfxlen),
+            Self::V6 { addr, pfxlen } => (&addr.octets(), *pfxlen),
+        };
+        let nbytes = (pfxlen as usize).div_ceil(8);
+        out.push(pfxlen);
+        out.extend_from_slice(&octets[..nbytes]);
+    }
+}
+
+/// One NLRI component of a FlowSpec rule.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum FlowSpecComponent {
+    /// Type 1
+    DestinationPrefix(FlowSpecPrefix),
+    /// Type 2
+    SourcePrefix(FlowSpecPrefix),
+    /// Type 3
+    Protocol(Vec<NumericMatch>),
+    /// Type 5
+    DestinationPort(Vec<NumericMatch>),
+    /// Type 6
+    SourcePort(Vec<NumericMatch>),
+}
+
+impl FlowSpecComponent {
+    fn type_and_body(&self) -> (u8, Vec<u8>) {
+        let mut body = Vec::new();
+        let ty = match self {
+            Self::DestinationPrefix(p) => {
+                p.encode(&mut body);
+                1
+            }
+            Self::SourcePrefix(p) => {
+                p.encode(&mut body);
+                2
+            }
+            Self::Protocol(ops) => {
+                ops.iter().for_each(|op| op.encode(&mut body));
+                3
+            }
+            Self::DestinationPort(ops) => {
+                ops.iter().for_each(|op| op.encode(&mut body));
+                5
+            }
+            Self::SourcePort(ops) => {
+                ops.iter().for_each(|op| op.encode(&mut body));
+                6
+            }
+        };
+        (ty, body)
+    }
+}
+
+/// Traffic-action extended community attached to a FlowSpec rule (RFC 5575 §7).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum TrafficAction {
+    /// `traffic-rate`: 2-byte AS number plus a 4-byte IEEE-754 rate in bytes/sec.
+    /// A rate of `0.0` means discard.
+    TrafficRate {
+        as_number: u16,
+        bytes_per_second: f32,
+    },
+    /// `traffic-action`: sample and/or terminal-action bits.
+    TrafficActionFlags { sample: bool, terminal: bool },
+}
+
+impl TrafficAction {
+    pub fn discard(as_number: u16) -> Self {
+        Self::TrafficRate {
+            as_number,
+            bytes_per_second: 0.0,
+        }
+    }
+
+    pub fn encode(&self) -> [u8; 8] {
+        let mut buf = [0u8; 8];
+        match self {
+            Self::TrafficRate {
+                as_number,
+                bytes_per_second,
+            } => {
+                buf[0] = 0x80;
+                buf[1] = 0x06;
+                buf[2..4].copy_from_slice(&as_number.to_be_bytes());
+                buf[4..8].copy_from_slice(&bytes_per_second.to_be_bytes());
+            }
+            Self::TrafficActionFlags { sample, terminal } => {
+                buf[0] = 0x80;
+                buf[1] = 0x07;
+                buf[7] = (*terminal as u8) | ((*sample as u8) << 1);
+            }
+        }
+        buf
+    }
+}
+
+/// A complete FlowSpec rule: an NLRI (list of components) paired with the
+/// extended communities describing the mitigation action.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct FlowSpecRule {
+    pub components: Vec<FlowSpecComponent>,
+    pub actions: Vec<TrafficAction>,
+}
+
+impl FlowSpecRule {
+    /// Encode just the NLRI (not the extended communities) into wire format.
+    pub fn encode_nlri(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        for component in &self.components {
+            let (ty, mut body) = component.type_and_body();
+            out.push(ty);
+            out.append(&mut body);
+        }
+        out
+    }
+}
+
+/// One offending aggregate key pulled out of a `FullAggregate`, with its
+/// observed packet count.
+#[derive(Debug, Clone, Copy)]
+pub struct OffendingKey {
+    pub proto: Proto,
+    pub src: IpAddr,
+    pub dst: IpAddr,
+    pub packets: u64,
+}
+
+/// Build a deduplicated, prefix-collapsed set of FlowSpec rules for every
+/// `(proto, dst)` group whose sources crossed `threshold` packets.
+///
+/// Sources that are adjacent `/32` (or `/128`) siblings sharing a parent are
+/// collapsed into the shorter covering prefix. The port component is omitted
+/// entirely for ICMP, which has no ports.
+pub fn build_rules(keys: &[OffendingKey], threshold: u64, mitigation_as: u16) -> Vec<FlowSpecRule> {
+    let mut groups: BTreeMap<(Proto, IpAddr), Vec<IpAddr>> = BTreeMap::new();
+    for key in keys {
+        if key.packets < threshold {
+            continue;
+        }
+        groups.entry((key.proto, key.dst)).or_default().push(key.src);
+    }
+
+    let mut rules = Vec::new();
+    for ((proto, dst), mut sources) in groups {
+        sources.sort();
+        sources.dedup();
+
+        let collapsed_prefixes = collapse_sources(&sources);
+        for src_prefix in collapsed_prefixes {
+            let mut components = vec![
+                FlowSpecComponent::DestinationPrefix(FlowSpecPrefix::host(dst)),
+                FlowSpecComponent::SourcePrefix(src_prefix),
+                FlowSpecComponent::Protocol(vec![NumericMatch::equals(proto.0 as u32)]),
+            ];
+            if proto != Proto::ICMP && proto != Proto::ICMP6 {
+                components.push(FlowSpecComponent::DestinationPort(vec![
+                    NumericMatch::equals(53),
+                ]));
+            }
+
+            rules.push(FlowSpecRule {
+                components,
+                actions: vec![
+                    TrafficAction::discard(mitigation_as),
+                    TrafficAction::TrafficActionFlags {
+                        sample: false,
+                        terminal: true,
+                    },
+                ],
+            });
+        }
+    }
+    rules
+}
+
+/// Collapse a sorted, deduplicated slice of host addresses into the minimal
+/// set of covering prefixes by repeatedly merging sibling `/N` pairs into
+/// their shared `/(N-1)` parent until a fixed point is reached.
+fn collapse_sources(sources: &[IpAddr]) -> Vec<FlowSpecPrefix> {
+    let mut v4: Vec<(u32, u8)> = sources
+        .iter()
+        .filter_map(|addr| match addr {
+            IpAddr::V4(addr) => Some((u32::from(*addr), 32)),
+            IpAddr::V6(_) => None,
+        })
+        .collect();
+    let mut v6: Vec<(u128, u8)> = sources
+        .iter()
+        .filter_map(|addr| match addr {
+            IpAddr::V6(addr) => Some((u128::from(*addr), 128)),
+            IpAddr::V4(_) => None,
+        })
+        .collect();
+
+    collapse_fixed_point_u32(&mut v4);
+    collapse_fixed_point_u128(&mut v6);
+
+    v4.into_iter()
+        .map(|(addr, pfxlen)| FlowSpecPrefix::V4 {
+            addr: Ipv4Addr::from(addr),
+            pfxlen,
+        })
+        .chain(v6.into_iter().map(|(addr, pfxlen)| FlowSpecPrefix::V6 {
+            addr: Ipv6Addr::from(addr),
+            pfxlen,
+        }))
+        .collect()
+}
+
+/// Sibling-merge pass over `/N` prefixes packed into a `u32` (IPv4).
+fn collapse_fixed_point_u32(prefixes: &mut Vec<(u32, u8)>) {
+    loop {
+        prefixes.sort_unstable();
+        prefixes.dedup();
+
+        let mut merged = Vec::with_capacity(prefixes.len());
+        let mut changed = false;
+        let mut iter = prefixes.iter().copied().peekable();
+        while let Some((addr, len)) = iter.next() {
+            if len == 0 {
+                merged.push((addr, len));
+                continue;
+            }
+            if let Some(&(next_addr, next_len)) = iter.peek() {
+                if next_len == len {
+                    let parent_mask = !0u32 << (32 - (len - 1) as u32);
+                    let sibling_bit = 1u32 << (32 - len as u32);
+                    if (addr & parent_mask) == (next_addr & parent_mask)
+                        && (addr ^ next_addr) == sibling_bit
+                    {
+                        merged.push((addr & parent_mask, len - 1));
+                        iter.next();
+                        changed = true;
+                        continue;
+                    }
+                }
+            }
+            merged.push((addr, len));
+        }
+
+        *prefixes = merged;
+        if !changed {
+            break;
+        }
+    }
+}
+
+/// Sibling-merge pass over `/N` prefixes packed into a `u128` (IPv6).
+fn collapse_fixed_point_u128(prefixes: &mut Vec<(u128, u8)>) {
+    loop {
+        prefixes.sort_unstable();
+        prefixes.dedup();
+
+        let mut merged = Vec::with_capacity(prefixes.len());
+        let mut changed = false;
+        let mut iter = prefixes.iter().copied().peekable();
+        while let Some((addr, len)) = iter.next() {
+            if len == 0 {
+                merged.push((addr, len));
+                continue;
+            }
+            if let Some(&(next_addr, next_len)) = iter.peek() {
+                if next_len == len {
+                    let parent_mask = !0u128 << (128 - (len - 1) as u32);
+                    let sibling_bit = 1u128 << (128 - len as u32);
+                    if (addr & parent_mask) == (next_addr & parent_mask)
+                        && (addr ^ next_addr) == sibling_bit
+                    {
+                        merged.push((addr & parent_mask, len - 1));
+                        iter.next();
+                        changed = true;
+                        continue;
+                    }
+                }
+            }
+            merged.push((addr, len));
+        }
+
+        *prefixes = merged;
+        if !changed {
+            break;
+        }
+    }
+}
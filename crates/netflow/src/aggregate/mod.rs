@@ -0,0 +1,470 @@
+use std::collections::BTreeMap;
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+use super::TcpFlags;
+
+pub mod flowspec;
+pub mod hyperloglog;
+pub mod prefix_summary;
+
+/// Per-flag packet counts used to surface classic TCP-based attack signatures.
+#[derive(Debug, Default, Clone, Copy, serde::Serialize, serde::Deserialize)]
+pub struct TcpFlagCounters {
+    /// SYN set, ACK not set: SYN flood candidate.
+    pub syn_no_ack: u64,
+    /// RST set: RST abuse/reset flood candidate.
+    pub rst: u64,
+    /// ACK set and no other flag: ACK flood candidate.
+    pub bare_ack: u64,
+}
+
+impl TcpFlagCounters {
+    fn observe(&mut self, flags: TcpFlags) {
+        if flags.has(TcpFlags::SYN) && !flags.has(TcpFlags::ACK) {
+            self.syn_no_ack += 1;
+        }
+        if flags.has(TcpFlags::RST) {
+            self.rst += 1;
+        }
+        if flags == TcpFlags::ACK {
+            self.bare_ack += 1;
+        }
+    }
+}
+
+impl std::ops::AddAssign for TcpFlagCounters {
+    fn add_assign(&mut self, rhs: Self) {
+        self.syn_no_ack += rhs.syn_no_ack;
+        self.rst += rhs.rst;
+        self.bare_ack += rhs.bare_ack;
+    }
+}
+
+/// Aggregate for a single IP address
+#[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
+pub struct IpAggregate {
+    pub total_packets: u64,
+    pub tcp_flags: TcpFlagCounters,
+    /// Approximate count of distinct real source addresses folded into this bucket, since `src`
+    /// groups many individual addresses (a whole announced network, or an origin AS) into one
+    /// key. See [`hyperloglog`] for how the sketch itself works.
+    ///
+    /// `#[serde(default)]` so aggregate files written before this field existed still deserialize,
+    /// just with an empty (zero-estimate) sketch.
+    #[serde(default)]
+    pub distinct_sources: hyperloglog::HyperLogLog,
+}
+
+impl IpAggregate {
+    /// Record one observed packet's TCP flags, updating the flag counters.
+    pub fn observe_tcp_flags(&mut self, flags: TcpFlags) {
+        self.tcp_flags.observe(flags);
+    }
+
+    /// Fold an already-hashed source address (see [`hyperloglog::hash_value`]) into the
+    /// distinct-source sketch.
+    pub fn observe_source_hash(&mut self, source_hash: u64) {
+        self.distinct_sources.observe_hash(source_hash);
+    }
+}
+
+impl std::ops::AddAssign for IpAggregate {
+    fn add_assign(&mut self, rhs: Self) {
+        self.total_packets += rhs.total_packets;
+        self.tcp_flags += rhs.tcp_flags;
+        self.distinct_sources += &rhs.distinct_sources;
+    }
+}
+
+/// A classic attack signature surfaced by crossing a configurable threshold
+/// of the per-source TCP flag distribution within a timestamp bucket.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TcpAttackSignature {
+    /// SYN:ACK ratio (bare SYNs vs. total packets) crossed the threshold.
+    SynFlood,
+    /// RST fraction of total packets crossed the threshold.
+    RstAbuse,
+    /// Bare-ACK fraction of total packets crossed the threshold.
+    AckFlood,
+}
+
+/// Classify a source's traffic using its [`IpAggregate`] and configurable
+/// thresholds (each in `0.0..=1.0`, expressed as a fraction of `total_packets`).
+pub fn classify_tcp_signature(
+    aggregate: &IpAggregate,
+    syn_ack_ratio_threshold: f64,
+    rst_fraction_threshold: f64,
+) -> Option<TcpAttackSignature> {
+    if aggregate.total_packets == 0 {
+        return None;
+    }
+    let total = aggregate.total_packets as f64;
+
+    if aggregate.tcp_flags.syn_no_ack as f64 / total >= syn_ack_ratio_threshold {
+        return Some(TcpAttackSignature::SynFlood);
+    }
+    if aggregate.tcp_flags.rst as f64 / total >= rst_fraction_threshold {
+        return Some(TcpAttackSignature::RstAbuse);
+    }
+    if aggregate.tcp_flags.bare_ack as f64 / total >= syn_ack_ratio_threshold {
+        return Some(TcpAttackSignature::AckFlood);
+    }
+
+    None
+}
+
+/// Identifies a packet's source in a [`FullAggregate`] key. Without a BGP routing table, this is
+/// the address masked to a flat fixed length (/24 for IPv4, /48 for IPv6);
+/// with one, it is the real announced network, or better yet its origin AS, so a botnet spread
+/// across one network's announced ranges collapses into a single key regardless of which
+/// specific prefix each bot sits in. See `netflow-aggregate`'s `--rib-dump`/`--mrt-dump`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, serde::Serialize, serde::Deserialize)]
+pub enum SourceKey<A> {
+    /// Grouped by real BGP origin AS, the preferred grouping once a routing table covers the
+    /// source.
+    OriginAs(u32),
+    /// No origin AS resolved for this source: either no routing table was supplied, or the
+    /// address isn't covered by any announced route. Falls back to the announced/masked network.
+    Network { network: A, prefix_len: u8 },
+}
+
+impl<A> SourceKey<A> {
+    /// Convert the network address type, e.g. widening `Ipv4Addr`/`Ipv6Addr` into `IpAddr`.
+    pub fn map_network<B>(self, f: impl FnOnce(A) -> B) -> SourceKey<B> {
+        match self {
+            SourceKey::OriginAs(asn) => SourceKey::OriginAs(asn),
+            SourceKey::Network { network, prefix_len } => SourceKey::Network {
+                network: f(network),
+                prefix_len,
+            },
+        }
+    }
+}
+
+/// Destination-address dictionary: interns addresses into small indices, so a
+/// [`FamilyAggregate`]'s packed [`AggregateKey`] carries a 4-byte index instead of a full address.
+/// In practice a capture sees a handful of distinct protected destinations receiving essentially
+/// all traffic, so the dictionary itself stays tiny while every per-flow key shrinks.
+#[derive(Debug)]
+struct DstDict<A> {
+    by_addr: BTreeMap<A, u32>,
+    addrs: Vec<A>,
+}
+
+impl<A> Default for DstDict<A> {
+    fn default() -> Self {
+        DstDict { by_addr: BTreeMap::new(), addrs: Vec::new() }
+    }
+}
+
+impl<A: Ord + Copy> DstDict<A> {
+    fn intern(&mut self, addr: A) -> u32 {
+        *self.by_addr.entry(addr).or_insert_with(|| {
+            self.addrs.push(addr);
+            self.addrs.len() as u32 - 1
+        })
+    }
+}
+
+impl<A: Copy> DstDict<A> {
+    fn resolve(&self, index: u32) -> A {
+        self.addrs[index as usize]
+    }
+}
+
+/// Packed, byte-aligned per-flow aggregation key. Built fresh for every packet and pushed onto a
+/// plain `Vec` rather than hashed/tree-inserted, so the hot aggregation loop pays for an
+/// amortized-`O(1)` push instead of a `BTreeMap` node allocation and rebalance on every packet;
+/// [`FamilyAggregateBuilder::finish`] pays the `O(n log n)` sort-and-merge cost exactly once per
+/// file instead. `#[repr(packed)]` drops the struct's otherwise-wasted alignment padding, which
+/// multiplies across the hundreds of millions of entries a multi-day capture produces.
+#[repr(Rust, packed)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct AggregateKey<A> {
+    timestamp: u32,
+    proto: super::Proto,
+    src: SourceKey<A>,
+    /// Index into the owning [`FamilyAggregate`]'s [`DstDict`], not a full destination address.
+    dst: u32,
+}
+
+/// The per-packet payload pushed onto [`FamilyAggregateBuilder::entries`]. Kept to the size of a
+/// flag byte and a hash rather than a full [`IpAggregate`]: the latter's `distinct_sources` sketch
+/// is a whole register array, and building one per packet (rather than once per merged key in
+/// [`FamilyAggregateBuilder::finish`]) would multiply peak memory by the sketch size across the
+/// hundreds of millions of per-packet entries a multi-day capture produces.
+#[derive(Debug, Clone, Copy)]
+struct RawObservation {
+    tcp_flags: TcpFlags,
+    source_hash: u64,
+}
+
+/// Accumulates one file's worth of per-packet observations for a single address family, ready to
+/// be folded into a [`FamilyAggregate`] by [`finish`](Self::finish).
+pub struct FamilyAggregateBuilder<A> {
+    dst_dict: DstDict<A>,
+    entries: Vec<(AggregateKey<A>, RawObservation)>,
+}
+
+// Can't `#[derive(Debug)]`: the packed `AggregateKey<A>` can only be formatted by copying its
+// fields out first, so its derived `Debug` impl (and thus this one) needs `A: Copy` rather than
+// the `A: Debug` a derive would normally ask for.
+impl<A: std::fmt::Debug + Copy> std::fmt::Debug for FamilyAggregateBuilder<A> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FamilyAggregateBuilder")
+            .field("dst_dict", &self.dst_dict)
+            .field("entries", &self.entries)
+            .finish()
+    }
+}
+
+impl<A> Default for FamilyAggregateBuilder<A> {
+    fn default() -> Self {
+        FamilyAggregateBuilder { dst_dict: DstDict::default(), entries: Vec::new() }
+    }
+}
+
+impl<A: Ord + Copy> FamilyAggregateBuilder<A> {
+    /// Record one observed packet's key and TCP flags. `raw_src` is the packet's real, unmasked
+    /// source address, hashed (but not yet folded into a sketch — see [`RawObservation`]) for the
+    /// bucket's eventual [`HyperLogLog`](hyperloglog::HyperLogLog) distinct-source estimate; `src`
+    /// is the (possibly masked/AS-grouped) key this packet is bucketed under.
+    pub fn observe(
+        &mut self,
+        timestamp: u32,
+        proto: super::Proto,
+        src: SourceKey<A>,
+        raw_src: impl std::hash::Hash,
+        dst: A,
+        flags: TcpFlags,
+    ) {
+        let dst = self.dst_dict.intern(dst);
+        let source_hash = hyperloglog::hash_value(raw_src);
+        self.entries.push((AggregateKey { timestamp, proto, src, dst }, RawObservation { tcp_flags: flags, source_hash }));
+    }
+
+    /// Sort the accumulated per-packet entries and coalesce every run of matching keys into a
+    /// single summed [`IpAggregate`] — the single point where this design pays a sort, and the
+    /// only point where a key's `distinct_sources` sketch is actually allocated, rather than on
+    /// every packet.
+    pub fn finish(mut self) -> FamilyAggregate<A> {
+        self.entries.sort_unstable_by_key(|(key, _)| *key);
+
+        let mut merged: Vec<(AggregateKey<A>, IpAggregate)> = Vec::with_capacity(self.entries.len());
+        for (key, raw) in self.entries {
+            let value = match merged.last_mut() {
+                Some((last_key, last_value)) if *last_key == key => last_value,
+                _ => {
+                    merged.push((key, IpAggregate::default()));
+                    &mut merged.last_mut().expect("just pushed").1
+                }
+            };
+            value.total_packets += 1;
+            value.observe_tcp_flags(raw.tcp_flags);
+            value.observe_source_hash(raw.source_hash);
+        }
+
+        FamilyAggregate { dst_dict: self.dst_dict, entries: merged }
+    }
+}
+
+/// Sorted, deduplicated aggregate for a single address family, built via [`FamilyAggregateBuilder`]
+/// and merged across files through its [`Add`](std::ops::Add) impl.
+pub struct FamilyAggregate<A> {
+    dst_dict: DstDict<A>,
+    entries: Vec<(AggregateKey<A>, IpAggregate)>,
+}
+
+// See the matching impl on `FamilyAggregateBuilder` for why this can't be `#[derive(Debug)]`.
+impl<A: std::fmt::Debug + Copy> std::fmt::Debug for FamilyAggregate<A> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FamilyAggregate")
+            .field("dst_dict", &self.dst_dict)
+            .field("entries", &self.entries)
+            .finish()
+    }
+}
+
+impl<A> Default for FamilyAggregate<A> {
+    fn default() -> Self {
+        FamilyAggregate { dst_dict: DstDict::default(), entries: Vec::new() }
+    }
+}
+
+impl<A> FamilyAggregate<A> {
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+impl<A: Ord + Copy> std::ops::Add for FamilyAggregate<A> {
+    type Output = Self;
+
+    fn add(mut self, rhs: Self) -> Self::Output {
+        if self.entries.is_empty() {
+            return rhs;
+        }
+        if rhs.entries.is_empty() {
+            return self;
+        }
+
+        // `rhs`'s dictionary indices are meaningless against `self`'s dictionary: remap every
+        // `rhs` entry's `dst` into `self`'s dictionary first, then re-sort just the `rhs` side
+        // (only `dst` moved, so `self`'s order is untouched) before a linear sorted merge.
+        let FamilyAggregate { dst_dict: rhs_dict, entries: mut rhs_entries } = rhs;
+        for (key, _) in rhs_entries.iter_mut() {
+            let addr = rhs_dict.resolve(key.dst);
+            key.dst = self.dst_dict.intern(addr);
+        }
+        rhs_entries.sort_unstable_by_key(|(key, _)| *key);
+
+        let mut merged = Vec::with_capacity(self.entries.len() + rhs_entries.len());
+        let mut left = self.entries.into_iter().peekable();
+        let mut right = rhs_entries.into_iter().peekable();
+        loop {
+            let ordering = match (left.peek(), right.peek()) {
+                (Some((lkey, _)), Some((rkey, _))) => Some(lkey.cmp(rkey)),
+                (Some(_), None) => Some(std::cmp::Ordering::Less),
+                (None, Some(_)) => Some(std::cmp::Ordering::Greater),
+                (None, None) => None,
+            };
+            match ordering {
+                Some(std::cmp::Ordering::Less) => merged.push(left.next().unwrap()),
+                Some(std::cmp::Ordering::Greater) => merged.push(right.next().unwrap()),
+                Some(std::cmp::Ordering::Equal) => {
+                    let (key, mut lvalue) = left.next().unwrap();
+                    let (_, rvalue) = right.next().unwrap();
+                    lvalue += rvalue;
+                    merged.push((key, lvalue));
+                }
+                None => break,
+            }
+        }
+
+        self.entries = merged;
+        self
+    }
+}
+
+/// Borrowing iterator over a [`FamilyAggregate`], reconstructing each entry's plain
+/// `(timestamp, proto, source, destination)` key from its packed, dictionary-interned storage.
+pub struct FamilyAggregateIter<'a, A> {
+    dst_dict: &'a DstDict<A>,
+    entries: std::slice::Iter<'a, (AggregateKey<A>, IpAggregate)>,
+}
+
+impl<'a, A: Copy> Iterator for FamilyAggregateIter<'a, A> {
+    type Item = ((u32, super::Proto, SourceKey<A>, A), &'a IpAggregate);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (key, value) = self.entries.next()?;
+        let AggregateKey { timestamp, proto, src, dst } = *key;
+        Some(((timestamp, proto, src, self.dst_dict.resolve(dst)), value))
+    }
+}
+
+impl<'a, A: Ord + Copy> IntoIterator for &'a FamilyAggregate<A> {
+    type Item = ((u32, super::Proto, SourceKey<A>, A), &'a IpAggregate);
+    type IntoIter = FamilyAggregateIter<'a, A>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        FamilyAggregateIter { dst_dict: &self.dst_dict, entries: self.entries.iter() }
+    }
+}
+
+/// Owning iterator over a [`FamilyAggregate`], see [`FamilyAggregateIter`].
+pub struct FamilyAggregateIntoIter<A> {
+    dst_dict: DstDict<A>,
+    entries: std::vec::IntoIter<(AggregateKey<A>, IpAggregate)>,
+}
+
+impl<A: Copy> Iterator for FamilyAggregateIntoIter<A> {
+    type Item = ((u32, super::Proto, SourceKey<A>, A), IpAggregate);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (key, value) = self.entries.next()?;
+        let AggregateKey { timestamp, proto, src, dst } = key;
+        Some(((timestamp, proto, src, self.dst_dict.resolve(dst)), value))
+    }
+}
+
+impl<A: Ord + Copy> IntoIterator for FamilyAggregate<A> {
+    type Item = ((u32, super::Proto, SourceKey<A>, A), IpAggregate);
+    type IntoIter = FamilyAggregateIntoIter<A>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        FamilyAggregateIntoIter { dst_dict: self.dst_dict, entries: self.entries.into_iter() }
+    }
+}
+
+impl<A: Ord + Copy + serde::Serialize> serde::Serialize for FamilyAggregate<A> {
+    /// Serializes as a flat array of `(key, value)` pairs, with destination addresses resolved
+    /// back out of the dictionary, so on-disk aggregate files never contain dictionary indices.
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeSeq;
+        let mut seq = serializer.serialize_seq(Some(self.entries.len()))?;
+        for entry in self {
+            seq.serialize_element(&entry)?;
+        }
+        seq.end()
+    }
+}
+
+impl<'de, A: Ord + Copy + serde::Deserialize<'de>> serde::Deserialize<'de> for FamilyAggregate<A> {
+    /// Unlike [`FamilyAggregateBuilder::finish`], there's no per-packet [`RawObservation`] to fold
+    /// here: every `value` already arrives as a fully-merged [`IpAggregate`] (sketch included) from
+    /// a previous [`serialize`](Self::serialize) call, so this builds `entries` directly rather
+    /// than routing through the builder's per-packet machinery.
+    #[allow(clippy::type_complexity)]
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let raw: Vec<((u32, super::Proto, SourceKey<A>, A), IpAggregate)> =
+            serde::Deserialize::deserialize(deserializer)?;
+
+        let mut dst_dict = DstDict::<A>::default();
+        let mut entries: Vec<(AggregateKey<A>, IpAggregate)> = Vec::with_capacity(raw.len());
+        for ((timestamp, proto, src, dst), value) in raw {
+            let dst = dst_dict.intern(dst);
+            entries.push((AggregateKey { timestamp, proto, src, dst }, value));
+        }
+        entries.sort_unstable_by_key(|(key, _)| *key);
+
+        Ok(FamilyAggregate { dst_dict, entries })
+    }
+}
+
+/// Aggregate for all Netflows together
+#[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
+pub struct FullAggregate {
+    /// IPv4 aggregates
+    pub ipv4: FamilyAggregate<Ipv4Addr>,
+    pub ipv6: FamilyAggregate<Ipv6Addr>,
+}
+
+impl std::ops::Add for FullAggregate {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        FullAggregate {
+            ipv4: self.ipv4 + rhs.ipv4,
+            ipv6: self.ipv6 + rhs.ipv6,
+        }
+    }
+}
+
+/// Per-file accumulator for both address families, finished into a [`FullAggregate`] once a file
+/// has been fully read.
+#[derive(Debug, Default)]
+pub struct FullAggregateBuilder {
+    pub ipv4: FamilyAggregateBuilder<Ipv4Addr>,
+    pub ipv6: FamilyAggregateBuilder<Ipv6Addr>,
+}
+
+impl FullAggregateBuilder {
+    pub fn finish(self) -> FullAggregate {
+        FullAggregate { ipv4: self.ipv4.finish(), ipv6: self.ipv6.finish() }
+    }
+}
@@ -0,0 +1,220 @@
+//! Collapse a set of source prefixes into their minimal equivalent CIDR cover.
+//!
+//! Without a BGP routing table, `aggregate_query_responses` pins every source to a fixed /24 (v4)
+//! / /48 (v6) mask, which produces huge, fragmented key sets when an
+//! attacker's traffic actually spans a much larger announced prefix. This collapses such a flat
+//! prefix set with the classic `aggregate6`-style merge: sort by (network, prefix length), drop
+//! any prefix already covered by a preceding one, then repeatedly merge sibling pairs (two
+//! prefixes of equal length `L` sharing the same length-`(L - 1)` parent) into that parent,
+//! iterating passes until nothing changes. `total_packets` is summed onto the merged parent at
+//! each step. Sources already resolved to a [`SourceKey::OriginAs`] are reported separately,
+//! summed per AS, since an AS has no single announced network to merge against its neighbours.
+
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+use super::{FullAggregate, SourceKey};
+
+/// One CIDR in the minimal cover produced by [`summarize_ipv4_prefixes`] /
+/// [`summarize_ipv6_prefixes`], carrying the summed `total_packets` of every more-specific prefix
+/// merged into it.
+#[derive(Debug, Clone, Copy, serde::Serialize, serde::Deserialize)]
+pub struct SummarizedPrefix<A> {
+    pub network: A,
+    pub prefix_len: u8,
+    pub total_packets: u64,
+}
+
+/// Minimal CIDR cover of every active source recorded in `aggregate`, summed across timestamps,
+/// protocols and destinations, plus the summed packet count of every source already resolved to
+/// an origin AS. Each [`SourceKey::Network`] entry already carries the prefix length it was keyed
+/// at (the flat /24 or /48 mask when no routing table was supplied, or the real
+/// announced prefix otherwise), so no separate `cidrv4`/`cidrv6` parameter is needed here.
+#[allow(clippy::type_complexity)]
+pub fn summarize_source_prefixes(
+    aggregate: &FullAggregate,
+) -> (
+    Vec<SummarizedPrefix<Ipv4Addr>>,
+    Vec<SummarizedPrefix<Ipv6Addr>>,
+    Vec<(u32, u64)>,
+) {
+    let mut as_counts: std::collections::BTreeMap<u32, u64> = std::collections::BTreeMap::new();
+
+    let mut v4_counts: std::collections::BTreeMap<(Ipv4Addr, u8), u64> =
+        std::collections::BTreeMap::new();
+    for ((_, _, src, _), ipagg) in &aggregate.ipv4 {
+        match src {
+            SourceKey::Network { network, prefix_len } => {
+                *v4_counts.entry((network, prefix_len)).or_default() += ipagg.total_packets
+            }
+            SourceKey::OriginAs(asn) => *as_counts.entry(asn).or_default() += ipagg.total_packets,
+        }
+    }
+    let v4 = summarize_ipv4_prefixes(
+        v4_counts
+            .into_iter()
+            .map(|((network, prefix_len), total_packets)| (network, prefix_len, total_packets)),
+    );
+
+    let mut v6_counts: std::collections::BTreeMap<(Ipv6Addr, u8), u64> =
+        std::collections::BTreeMap::new();
+    for ((_, _, src, _), ipagg) in &aggregate.ipv6 {
+        match src {
+            SourceKey::Network { network, prefix_len } => {
+                *v6_counts.entry((network, prefix_len)).or_default() += ipagg.total_packets
+            }
+            SourceKey::OriginAs(asn) => *as_counts.entry(asn).or_default() += ipagg.total_packets,
+        }
+    }
+    let v6 = summarize_ipv6_prefixes(
+        v6_counts
+            .into_iter()
+            .map(|((network, prefix_len), total_packets)| (network, prefix_len, total_packets)),
+    );
+
+    (v4, v6, as_counts.into_iter().collect())
+}
+
+/// Collapse `entries` (network, prefix length, packet count) into their minimal IPv4 CIDR cover.
+pub fn summarize_ipv4_prefixes(
+    entries: impl IntoIterator<Item = (Ipv4Addr, u8, u64)>,
+) -> Vec<SummarizedPrefix<Ipv4Addr>> {
+    let prefixes = entries
+        .into_iter()
+        .map(|(network, prefix_len, total_packets)| {
+            (u32::from_be_bytes(network.octets()), prefix_len, total_packets)
+        })
+        .collect();
+    merge_prefixes(prefixes, mask_v4)
+        .into_iter()
+        .map(|(network, prefix_len, total_packets)| SummarizedPrefix {
+            network: Ipv4Addr::from(network),
+            prefix_len,
+            total_packets,
+        })
+        .collect()
+}
+
+/// Collapse `entries` (network, prefix length, packet count) into their minimal IPv6 CIDR cover.
+pub fn summarize_ipv6_prefixes(
+    entries: impl IntoIterator<Item = (Ipv6Addr, u8, u64)>,
+) -> Vec<SummarizedPrefix<Ipv6Addr>> {
+    let prefixes = entries
+        .into_iter()
+        .map(|(network, prefix_len, total_packets)| {
+            (u128::from_be_bytes(network.octets()), prefix_len, total_packets)
+        })
+        .collect();
+    merge_prefixes(prefixes, mask_v6)
+        .into_iter()
+        .map(|(network, prefix_len, total_packets)| SummarizedPrefix {
+            network: Ipv6Addr::from(network),
+            prefix_len,
+            total_packets,
+        })
+        .collect()
+}
+
+/// Classic CIDR-merge fixed point: drop contained prefixes, merge siblings, repeat until a pass
+/// changes nothing. Generic over the address width via `mask`, mirroring how
+/// `ipv4_to_network_address`/`ipv6_to_network_address` stay separate rather than sharing a
+/// generic integer representation.
+fn merge_prefixes<Addr>(
+    mut prefixes: Vec<(Addr, u8, u64)>,
+    mask: impl Fn(Addr, u8) -> Addr,
+) -> Vec<(Addr, u8, u64)>
+where
+    Addr: Copy + Ord,
+{
+    loop {
+        prefixes.sort_by_key(|&(network, prefix_len, _)| (network, prefix_len));
+
+        // Drop any prefix fully contained in a preceding, equal-or-shorter one, folding its
+        // packet count into that parent.
+        let mut deduped: Vec<(Addr, u8, u64)> = Vec::with_capacity(prefixes.len());
+        for (network, prefix_len, total_packets) in prefixes {
+            if let Some(&mut (parent_network, parent_len, ref mut parent_packets)) =
+                deduped.last_mut()
+            {
+                if parent_len <= prefix_len && mask(network, parent_len) == parent_network {
+                    *parent_packets += total_packets;
+                    continue;
+                }
+            }
+            deduped.push((network, prefix_len, total_packets));
+        }
+
+        // Merge sibling pairs: two equal-length prefixes sharing the same one-shorter parent.
+        // Sorting by (network, prefix_len) above puts each such pair adjacent, bit `prefix_len -
+        // 1` clear before set.
+        let mut merged: Vec<(Addr, u8, u64)> = Vec::with_capacity(deduped.len());
+        let mut changed = false;
+        let mut iter = deduped.into_iter().peekable();
+        while let Some((network, prefix_len, total_packets)) = iter.next() {
+            if prefix_len > 0 {
+                if let Some(&(next_network, next_len, next_packets)) = iter.peek() {
+                    if next_len == prefix_len
+                        && mask(network, prefix_len - 1) == mask(next_network, prefix_len - 1)
+                    {
+                        merged.push((
+                            mask(network, prefix_len - 1),
+                            prefix_len - 1,
+                            total_packets + next_packets,
+                        ));
+                        iter.next();
+                        changed = true;
+                        continue;
+                    }
+                }
+            }
+            merged.push((network, prefix_len, total_packets));
+        }
+
+        prefixes = merged;
+        if !changed {
+            return prefixes;
+        }
+    }
+}
+
+fn mask_v4(addr: u32, prefix_len: u8) -> u32 {
+    if prefix_len == 0 {
+        return 0;
+    }
+    assert!(prefix_len <= 32, "CIDR for IPv4 must be <= 32");
+    let mask = !((1u32 << (32 - prefix_len)) - 1);
+    addr & mask
+}
+
+fn mask_v6(addr: u128, prefix_len: u8) -> u128 {
+    if prefix_len == 0 {
+        return 0;
+    }
+    assert!(prefix_len <= 128, "CIDR for IPv6 must be <= 128");
+    let mask = !((1u128 << (128 - prefix_len)) - 1);
+    addr & mask
+}
+
+#[test]
+fn test_merge_sibling_pair() {
+    let entries = [
+        ("198.51.100.0".parse().unwrap(), 25, 10),
+        ("198.51.100.128".parse().unwrap(), 25, 20),
+    ];
+    let summary = summarize_ipv4_prefixes(entries);
+    assert_eq!(summary.len(), 1);
+    assert_eq!(summary[0].network, "198.51.100.0".parse::<Ipv4Addr>().unwrap());
+    assert_eq!(summary[0].prefix_len, 24);
+    assert_eq!(summary[0].total_packets, 30);
+}
+
+#[test]
+fn test_contained_prefix_is_dropped() {
+    let entries = [
+        ("198.51.100.0".parse().unwrap(), 24, 10),
+        ("198.51.100.5".parse().unwrap(), 32, 5),
+    ];
+    let summary = summarize_ipv4_prefixes(entries);
+    assert_eq!(summary.len(), 1);
+    assert_eq!(summary[0].prefix_len, 24);
+    assert_eq!(summary[0].total_packets, 15);
+}
@@ -0,0 +1,455 @@
+//! Correlate flow endpoints with a live BGP routing view.
+//!
+//! Holds a prefix→route longest-prefix-match table, fed from a static RIB
+//! dump or a streaming BGP/BMP session, and used to fill/validate the
+//! `src_as`/`dst_as` fields on ingested flows so DDoS reports can rank
+//! offending autonomous systems rather than just individual IPs.
+
+use std::net::IpAddr;
+use std::sync::RwLock;
+
+use ipnetwork::{IpNetwork, Ipv4Network, Ipv6Network};
+
+/// Number of AS-path hops kept per route, truncated from the origin end, to
+/// bound per-entry memory.
+const MAX_PATH_HOPS: usize = 4;
+
+const MRT_TYPE_TABLE_DUMP_V2: u16 = 13;
+const SUBTYPE_RIB_IPV4_UNICAST: u16 = 2;
+const SUBTYPE_RIB_IPV6_UNICAST: u16 = 4;
+const BGP_ATTR_AS_PATH: u8 = 2;
+const ATTR_FLAG_EXTENDED_LENGTH: u8 = 0x10;
+
+/// Routing information for one prefix.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RouteInfo {
+    /// AS-path suffix closest to the origin, origin-first, zero-padded.
+    pub as_path_suffix: [u32; MAX_PATH_HOPS],
+    /// Number of hops actually present in `as_path_suffix`.
+    pub path_len: u8,
+    pub local_pref: u16,
+    pub med: u16,
+    pub next_hop: IpAddr,
+}
+
+impl RouteInfo {
+    pub fn origin_as(&self) -> u32 {
+        if self.path_len == 0 {
+            0
+        } else {
+            self.as_path_suffix[0]
+        }
+    }
+}
+
+fn bit_at<const N: usize>(addr: &[u8; N], index: u8) -> usize {
+    let byte = addr[(index / 8) as usize];
+    ((byte >> (7 - (index % 8))) & 1) as usize
+}
+
+#[derive(Debug, Default)]
+struct TrieNode {
+    route: Option<RouteInfo>,
+    children: [Option<Box<TrieNode>>; 2],
+}
+
+/// Binary Patricia/radix trie supporting longest-prefix-match lookup plus
+/// in-place announce/withdraw, so updates only touch the nodes along the
+/// affected prefix's path instead of reallocating the whole structure.
+#[derive(Debug, Default)]
+struct RouteTrie<const N: usize> {
+    root: TrieNode,
+}
+
+impl<const N: usize> RouteTrie<N> {
+    fn insert(&mut self, addr: [u8; N], pfxlen: u8, route: RouteInfo) {
+        let mut node = &mut self.root;
+        for i in 0..pfxlen {
+            node = node.children[bit_at(&addr, i)].get_or_insert_with(Default::default);
+        }
+        node.route = Some(route);
+    }
+
+    fn withdraw(&mut self, addr: [u8; N], pfxlen: u8) {
+        let mut node = &mut self.root;
+        for i in 0..pfxlen {
+            match node.children[bit_at(&addr, i)].as_mut() {
+                Some(child) => node = child,
+                None => return,
+            }
+        }
+        node.route = None;
+    }
+
+    fn lookup(&self, addr: &[u8; N]) -> Option<RouteInfo> {
+        self.lookup_with_prefix_len(addr).map(|(route, _)| route)
+    }
+
+    /// Longest-prefix-match lookup of the route covering `addr`, plus the length of the matched
+    /// prefix, so callers can report the actual announced network rather than just its route.
+    fn lookup_with_prefix_len(&self, addr: &[u8; N]) -> Option<(RouteInfo, u8)> {
+        let mut node = &self.root;
+        let mut best = node.route.map(|route| (route, 0));
+        for i in 0..(N as u8 * 8) {
+            match &node.children[bit_at(addr, i)] {
+                Some(child) => {
+                    node = child;
+                    if let Some(route) = node.route {
+                        best = Some((route, i + 1));
+                    }
+                }
+                None => break,
+            }
+        }
+        best
+    }
+}
+
+/// Prefix→route table, covering both address families, updatable
+/// concurrently with lookups via a reader-writer lock per family.
+#[derive(Debug, Default)]
+pub struct RouteTable {
+    v4: RwLock<RouteTrie<4>>,
+    v6: RwLock<RouteTrie<16>>,
+}
+
+impl RouteTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Install or update the route for `prefix/pfxlen`, as from a BGP/BMP
+    /// UPDATE announcement.
+    pub fn announce(&self, prefix: IpAddr, pfxlen: u8, route: RouteInfo) {
+        match prefix {
+            IpAddr::V4(addr) => self
+                .v4
+                .write()
+                .expect("lock poisoned")
+                .insert(addr.octets(), pfxlen, route),
+            IpAddr::V6(addr) => self
+                .v6
+                .write()
+                .expect("lock poisoned")
+                .insert(addr.octets(), pfxlen, route),
+        }
+    }
+
+    /// Remove the route for `prefix/pfxlen`, as from a BGP/BMP withdrawal.
+    pub fn withdraw(&self, prefix: IpAddr, pfxlen: u8) {
+        match prefix {
+            IpAddr::V4(addr) => self
+                .v4
+                .write()
+                .expect("lock poisoned")
+                .withdraw(addr.octets(), pfxlen),
+            IpAddr::V6(addr) => self
+                .v6
+                .write()
+                .expect("lock poisoned")
+                .withdraw(addr.octets(), pfxlen),
+        }
+    }
+
+    /// Longest-prefix-match lookup of the route covering `addr`.
+    pub fn lookup(&self, addr: IpAddr) -> Option<RouteInfo> {
+        match addr {
+            IpAddr::V4(addr) => self.v4.read().expect("lock poisoned").lookup(&addr.octets()),
+            IpAddr::V6(addr) => self.v6.read().expect("lock poisoned").lookup(&addr.octets()),
+        }
+    }
+
+    /// Longest-prefix-match lookup of the route covering `addr`, plus the announced network it
+    /// came from, so aggregation code can key on the real routing granularity instead of a flat
+    /// fixed-length mask.
+    pub fn lookup_with_prefix(&self, addr: IpAddr) -> Option<(RouteInfo, IpNetwork)> {
+        match addr {
+            IpAddr::V4(addr) => {
+                let (route, pfxlen) = self
+                    .v4
+                    .read()
+                    .expect("lock poisoned")
+                    .lookup_with_prefix_len(&addr.octets())?;
+                let network = Ipv4Network::new(addr, pfxlen).ok()?;
+                Some((route, IpNetwork::V4(Ipv4Network::new(network.network(), pfxlen).ok()?)))
+            }
+            IpAddr::V6(addr) => {
+                let (route, pfxlen) = self
+                    .v6
+                    .read()
+                    .expect("lock poisoned")
+                    .lookup_with_prefix_len(&addr.octets())?;
+                let network = Ipv6Network::new(addr, pfxlen).ok()?;
+                Some((route, IpNetwork::V6(Ipv6Network::new(network.network(), pfxlen).ok()?)))
+            }
+        }
+    }
+
+    /// Seed the table from a static RIB dump: one `prefix,origin_as[,next_hop]`
+    /// CSV record per line. This is a plain-text alternative to
+    /// [`load_mrt_dump`](Self::load_mrt_dump), enough to bootstrap the table before a
+    /// streaming BGP/BMP session takes over announcements/withdrawals.
+    pub fn load_rib_dump(&self, text: &str) -> Result<(), String> {
+        for line in text.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let mut fields = line.split(',');
+            let cidr = fields
+                .next()
+                .ok_or_else(|| format!("missing prefix in RIB line: {line}"))?;
+            let origin_as: u32 = fields
+                .next()
+                .ok_or_else(|| format!("missing origin AS in RIB line: {line}"))?
+                .parse()
+                .map_err(|_| format!("invalid origin AS in RIB line: {line}"))?;
+            let next_hop: IpAddr = fields
+                .next()
+                .unwrap_or("0.0.0.0")
+                .parse()
+                .map_err(|_| format!("invalid next hop in RIB line: {line}"))?;
+
+            let (addr, pfxlen) = cidr
+                .split_once('/')
+                .ok_or_else(|| format!("invalid CIDR in RIB line: {line}"))?;
+            let pfxlen: u8 = pfxlen
+                .parse()
+                .map_err(|_| format!("invalid prefix length in RIB line: {line}"))?;
+            let addr: IpAddr = addr
+                .parse()
+                .map_err(|_| format!("invalid address in RIB line: {line}"))?;
+
+            let mut as_path_suffix = [0u32; MAX_PATH_HOPS];
+            as_path_suffix[0] = origin_as;
+            self.announce(
+                addr,
+                pfxlen,
+                RouteInfo {
+                    as_path_suffix,
+                    path_len: 1,
+                    local_pref: 100,
+                    med: 0,
+                    next_hop,
+                },
+            );
+        }
+        Ok(())
+    }
+
+    /// Seed the table from a full MRT `TABLE_DUMP_V2` RIB dump (RFC 6396), installing every
+    /// `RIB_IPV4_UNICAST`/`RIB_IPV6_UNICAST` entry found. Only the origin AS (the rightmost hop
+    /// of the `AS_PATH` attribute) is kept; all other BGP attributes are ignored, and ASNs are
+    /// assumed to be encoded as 4 bytes. Non-`TABLE_DUMP_V2` records, and any other
+    /// `TABLE_DUMP_V2` subtype (e.g. the leading `PEER_INDEX_TABLE`), are skipped rather than
+    /// rejected.
+    pub fn load_mrt_dump(&self, data: &[u8]) -> Result<(), String> {
+        let mut offset = 0;
+        while offset < data.len() {
+            let header = mrt_read_bytes(data, offset, 12)?;
+            let mrt_type = u16::from_be_bytes(header[4..6].try_into().unwrap());
+            let subtype = u16::from_be_bytes(header[6..8].try_into().unwrap());
+            let length = u32::from_be_bytes(header[8..12].try_into().unwrap()) as usize;
+            offset += 12;
+            let payload = mrt_read_bytes(data, offset, length)?;
+            offset += length;
+
+            if mrt_type != MRT_TYPE_TABLE_DUMP_V2 {
+                continue;
+            }
+            match subtype {
+                SUBTYPE_RIB_IPV4_UNICAST => self.load_mrt_rib_ipv4(payload)?,
+                SUBTYPE_RIB_IPV6_UNICAST => self.load_mrt_rib_ipv6(payload)?,
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn load_mrt_rib_ipv4(&self, payload: &[u8]) -> Result<(), String> {
+        let mut offset = 4; // sequence_number
+        let pfxlen = *payload.get(offset).ok_or("truncated RIB entry prefix")?;
+        let nbytes = (pfxlen as usize).div_ceil(8);
+        if nbytes > 4 {
+            return Err(format!("IPv4 prefix length out of range: {pfxlen}"));
+        }
+        offset += 1;
+        let mut addr = [0u8; 4];
+        addr[..nbytes].copy_from_slice(mrt_read_bytes(payload, offset, nbytes)?);
+        offset += nbytes;
+
+        let entry_count =
+            u16::from_be_bytes(mrt_read_bytes(payload, offset, 2)?.try_into().unwrap());
+        offset += 2;
+        for _ in 0..entry_count {
+            let Some((origin_as, next_hop)) = self.read_mrt_rib_entry(payload, &mut offset)? else {
+                continue;
+            };
+            let mut as_path_suffix = [0u32; MAX_PATH_HOPS];
+            as_path_suffix[0] = origin_as;
+            self.announce(
+                std::net::Ipv4Addr::from(addr).into(),
+                pfxlen,
+                RouteInfo {
+                    as_path_suffix,
+                    path_len: 1,
+                    local_pref: 100,
+                    med: 0,
+                    next_hop: next_hop.unwrap_or(std::net::Ipv4Addr::UNSPECIFIED.into()),
+                },
+            );
+        }
+        Ok(())
+    }
+
+    fn load_mrt_rib_ipv6(&self, payload: &[u8]) -> Result<(), String> {
+        let mut offset = 4; // sequence_number
+        let pfxlen = *payload.get(offset).ok_or("truncated RIB entry prefix")?;
+        let nbytes = (pfxlen as usize).div_ceil(8);
+        if nbytes > 16 {
+            return Err(format!("IPv6 prefix length out of range: {pfxlen}"));
+        }
+        offset += 1;
+        let mut addr = [0u8; 16];
+        addr[..nbytes].copy_from_slice(mrt_read_bytes(payload, offset, nbytes)?);
+        offset += nbytes;
+
+        let entry_count =
+            u16::from_be_bytes(mrt_read_bytes(payload, offset, 2)?.try_into().unwrap());
+        offset += 2;
+        for _ in 0..entry_count {
+            let Some((origin_as, next_hop)) = self.read_mrt_rib_entry(payload, &mut offset)? else {
+                continue;
+            };
+            let mut as_path_suffix = [0u32; MAX_PATH_HOPS];
+            as_path_suffix[0] = origin_as;
+            self.announce(
+                std::net::Ipv6Addr::from(addr).into(),
+                pfxlen,
+                RouteInfo {
+                    as_path_suffix,
+                    path_len: 1,
+                    local_pref: 100,
+                    med: 0,
+                    next_hop: next_hop.unwrap_or(std::net::Ipv6Addr::UNSPECIFIED.into()),
+                },
+            );
+        }
+        Ok(())
+    }
+
+    /// Consume one RIB entry (peer index, originated time, BGP attributes) from `payload` at
+    /// `*offset`, advancing it, and return the origin AS extracted from its `AS_PATH` attribute.
+    /// The paired next hop is always `None`: the `NEXT_HOP` attribute is not read, so routes
+    /// loaded from an MRT dump carry an unspecified next hop.
+    fn read_mrt_rib_entry(
+        &self,
+        payload: &[u8],
+        offset: &mut usize,
+    ) -> Result<Option<(u32, Option<IpAddr>)>, String> {
+        *offset += 2; // peer_index
+        *offset += 4; // originated_time
+        let attr_len =
+            u16::from_be_bytes(mrt_read_bytes(payload, *offset, 2)?.try_into().unwrap()) as usize;
+        *offset += 2;
+        let attrs = mrt_read_bytes(payload, *offset, attr_len)?;
+        *offset += attr_len;
+
+        Ok(mrt_origin_as_from_attributes(attrs).map(|origin_as| (origin_as, None)))
+    }
+}
+
+/// Fill `src_as`/`dst_as` on `flow` from the routing table when they are
+/// unset, and return the origin AS of the flow's source so aggregation code
+/// can rank offending autonomous systems.
+pub fn enrich_flow(flow: &mut crate::NfdumpOutput, table: &RouteTable) -> Option<u32> {
+    let dst_addr: Option<IpAddr> = flow
+        .dst4_addr
+        .map(IpAddr::V4)
+        .or_else(|| flow.dst6_addr.map(IpAddr::V6));
+    if flow.dst_as == 0 {
+        if let Some(dst_route) = dst_addr.and_then(|addr| table.lookup(addr)) {
+            flow.dst_as = dst_route.origin_as();
+        }
+    }
+
+    let src_addr: IpAddr = if let Some(addr) = flow.src4_addr {
+        addr.into()
+    } else if let Some(addr) = flow.src6_addr {
+        addr.into()
+    } else {
+        return None;
+    };
+
+    let origin_as = table.lookup(src_addr)?.origin_as();
+    if flow.src_as == 0 {
+        flow.src_as = origin_as;
+    }
+
+    Some(origin_as)
+}
+
+fn mrt_read_bytes(data: &[u8], offset: usize, len: usize) -> Result<&[u8], String> {
+    data.get(offset..offset + len)
+        .ok_or_else(|| "truncated MRT record".to_string())
+}
+
+/// Walk a BGP path-attribute list and return the origin AS (the rightmost AS
+/// of the `AS_PATH` attribute), if present.
+fn mrt_origin_as_from_attributes(attrs: &[u8]) -> Option<u32> {
+    let mut offset = 0;
+    let mut origin_as = None;
+    while offset < attrs.len() {
+        let flags = *attrs.get(offset)?;
+        let type_code = *attrs.get(offset + 1)?;
+        offset += 2;
+        let len = if flags & ATTR_FLAG_EXTENDED_LENGTH != 0 {
+            let len = u16::from_be_bytes(attrs.get(offset..offset + 2)?.try_into().ok()?) as usize;
+            offset += 2;
+            len
+        } else {
+            let len = *attrs.get(offset)? as usize;
+            offset += 1;
+            len
+        };
+        let value = attrs.get(offset..offset + len)?;
+        offset += len;
+
+        if type_code == BGP_ATTR_AS_PATH {
+            origin_as = mrt_origin_as_from_as_path(value).or(origin_as);
+        }
+    }
+    origin_as
+}
+
+/// The origin AS is the last AS of the last segment of an `AS_PATH`
+/// attribute, regardless of whether that segment is an `AS_SEQUENCE` or an
+/// `AS_SET`.
+fn mrt_origin_as_from_as_path(value: &[u8]) -> Option<u32> {
+    let mut offset = 0;
+    let mut last_as = None;
+    while offset < value.len() {
+        let seg_len = *value.get(offset + 1)? as usize;
+        offset += 2;
+        for i in 0..seg_len {
+            let as_bytes = value.get(offset + i * 4..offset + i * 4 + 4)?;
+            last_as = Some(u32::from_be_bytes(as_bytes.try_into().ok()?));
+        }
+        offset += seg_len * 4;
+    }
+    last_as
+}
+
+#[test]
+fn test_route_table_longest_prefix_match() {
+    let table = RouteTable::new();
+    table.load_rib_dump("198.51.100.0/24,64500,198.51.100.1\n").unwrap();
+
+    let route = table.lookup("198.51.100.53".parse().unwrap()).unwrap();
+    assert_eq!(route.origin_as(), 64500);
+    assert!(table.lookup("203.0.113.1".parse().unwrap()).is_none());
+
+    table.withdraw("198.51.100.0".parse().unwrap(), 24);
+    assert!(table.lookup("198.51.100.53".parse().unwrap()).is_none());
+}
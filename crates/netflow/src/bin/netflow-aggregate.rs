@@ -3,8 +3,10 @@
 #![deny(unused_import_braces, unused_qualifications)]
 
 use color_eyre::eyre::{bail, Context as _, Result};
-use netflow::aggregate::{FullAggregate, IpAggregate};
-use netflow::is_for_target_cctld;
+use netflow::aggregate::prefix_summary::summarize_source_prefixes;
+use netflow::aggregate::{FullAggregate, FullAggregateBuilder, IpAggregate, SourceKey};
+use netflow::bgp::RouteTable;
+use netflow::{is_for_target_cctld, TargetSet};
 use rayon::prelude::*;
 use std::io::{BufRead, BufReader};
 use std::mem;
@@ -19,6 +21,18 @@ use std::process::Stdio;
 struct CliArgs {
     #[clap(long = "output")]
     output: PathBuf,
+    /// JSON file with a list of CIDR strings describing the protected ccTLD's
+    /// authoritative-nameserver address ranges, e.g. `["198.51.100.0/24", "2001:db8::/32"]`.
+    #[clap(long = "target-set")]
+    target_set: PathBuf,
+    /// CSV RIB dump (`prefix,origin_as[,next_hop]` per line) to key sources by their real
+    /// announced network/origin AS instead of a flat /24 (v4) / /48 (v6) mask. Mutually exclusive
+    /// with `--mrt-dump`.
+    #[clap(long = "rib-dump", conflicts_with = "mrt_dump")]
+    rib_dump: Option<PathBuf>,
+    /// Full MRT `TABLE_DUMP_V2` RIB dump, as an alternative to `--rib-dump`.
+    #[clap(long = "mrt-dump")]
+    mrt_dump: Option<PathBuf>,
     #[clap(subcommand)]
     agg: AggregateType,
 
@@ -40,6 +54,16 @@ enum AggregateType {
         #[clap(long = "agg-interval")]
         agg_interval: u32,
     },
+    /// Minimal-CIDR-cover summary of the active source prefixes, instead of the millions of flat
+    /// /24 (v4) / /48 (v6) rows `TrafficVolume` produces.
+    PrefixSummary {
+        /// Discard all data before this timestamp. Usefull to limit the aggregation in size.
+        #[clap(long = "time-start")]
+        time_start: Option<u32>,
+        /// Discard all data after this timestamp. Usefull to limit the aggregation in size.
+        #[clap(long = "time-end")]
+        time_end: Option<u32>,
+    },
 }
 
 fn main() -> Result<()> {
@@ -47,6 +71,30 @@ fn main() -> Result<()> {
     env_logger::init();
     let args: CliArgs = clap::Parser::parse();
 
+    let target_set_cidrs: Vec<String> =
+        serde_json::from_str(&std::fs::read_to_string(&args.target_set)?)?;
+    let target_set = TargetSet::from_cidrs(target_set_cidrs.iter().map(String::as_str))
+        .map_err(|err| color_eyre::eyre::eyre!(err))
+        .context("Failed to load target set")?;
+
+    let route_table = if let Some(path) = &args.rib_dump {
+        let table = RouteTable::new();
+        table
+            .load_rib_dump(&std::fs::read_to_string(path)?)
+            .map_err(|err| color_eyre::eyre::eyre!(err))
+            .context("Failed to load RIB dump")?;
+        Some(table)
+    } else if let Some(path) = &args.mrt_dump {
+        let table = RouteTable::new();
+        table
+            .load_mrt_dump(&std::fs::read(path)?)
+            .map_err(|err| color_eyre::eyre::eyre!(err))
+            .context("Failed to load MRT dump")?;
+        Some(table)
+    } else {
+        None
+    };
+
     match args.agg {
         AggregateType::TrafficVolume {
             time_start,
@@ -61,8 +109,14 @@ fn main() -> Result<()> {
                 "Each aggregation entry consumes at least {} bytes",
                 mem::size_of::<IpAggregate>()
             );
-            let aggregate: FullAggregate =
-                aggregate_query_responses(&args.files, time_start, time_end, agg_interval)?;
+            let aggregate: FullAggregate = aggregate_query_responses(
+                &args.files,
+                time_start,
+                time_end,
+                agg_interval,
+                &target_set,
+                route_table.as_ref(),
+            )?;
             log::info!(
                 "Found {} IPv4 and {} IPV6 entries.",
                 aggregate.ipv4.len(),
@@ -72,6 +126,35 @@ fn main() -> Result<()> {
             serialize_to_file(args.output, &aggregate)?;
             log::info!("Finished writing the output file.");
         }
+        AggregateType::PrefixSummary {
+            time_start,
+            time_end,
+        } => {
+            let time_start = time_start.unwrap_or(0);
+            let time_end = time_end.unwrap_or(u32::MAX);
+
+            log::info!("Start aggregating input files.");
+            // Timestamp bucketing doesn't matter here, only the source prefix does: collapse
+            // everything into a single bucket rather than paying for `TrafficVolume`'s
+            // finer-grained grouping.
+            let aggregate: FullAggregate = aggregate_query_responses(
+                &args.files,
+                time_start,
+                time_end,
+                u32::MAX,
+                &target_set,
+                route_table.as_ref(),
+            )?;
+            let (ipv4, ipv6, origin_as) = summarize_source_prefixes(&aggregate);
+            log::info!(
+                "Collapsed into {} IPv4 prefixes, {} IPv6 prefixes and {} origin ASes.",
+                ipv4.len(),
+                ipv6.len(),
+                origin_as.len()
+            );
+            serialize_to_file(args.output, &(ipv4, ipv6, origin_as))?;
+            log::info!("Finished writing the output file.");
+        }
     }
 
     Ok(())
@@ -87,6 +170,8 @@ fn aggregate_query_responses(
     time_start: u32,
     time_end: u32,
     agg_interval: u32,
+    target_set: &TargetSet,
+    route_table: Option<&RouteTable>,
 ) -> Result<FullAggregate> {
     assert!(
         time_start < time_end,
@@ -113,7 +198,7 @@ fn aggregate_query_responses(
                 .stdout(Stdio::piped())
                 .spawn()?;
 
-            let mut aggregate = FullAggregate::default();
+            let mut aggregate = FullAggregateBuilder::default();
             let mut nfdump_json = BufReader::new(nfdump.stdout.take().expect("Must exist"));
             // Temporary buffer, holds up to one JSON object
             let mut object = String::with_capacity(1024 * 10);
@@ -136,12 +221,11 @@ fn aggregate_query_responses(
 
                 let netflow: netflow::NfdumpOutput = serde_json::from_str(&object)
                     .with_context(|| format!("Original JSON: {object}"))?;
-                if !is_for_target_cctld(&netflow) {
+                if !is_for_target_cctld(&netflow, target_set) {
                     continue;
                 }
 
                 for packet in netflow::split_flow(netflow) {
-                    let src_ip = ip_to_network_address(packet.src_addr, 24, 48);
                     let dst_ip = packet.dst_addr;
                     let seconds = packet.time.timestamp() as u32;
                     // Round to aggregation interval
@@ -150,32 +234,40 @@ fn aggregate_query_responses(
                         // Abort early if the timestamp is outside of the range we care about
                         continue;
                     }
-                    let ipaggregate = match src_ip {
-                        IpAddr::V4(ipv4) => {
-                            let dst_v4 = if let IpAddr::V4(dst_v4) = dst_ip {
-                                dst_v4
+                    match dst_ip {
+                        IpAddr::V4(dst_v4) => {
+                            let src_v4 = if let IpAddr::V4(src_v4) = packet.src_addr {
+                                src_v4
                             } else {
-                                panic!("Destination IP must be of same type as source ip.");
+                                panic!("Source IP must be of same type as destination ip.");
                             };
-                            aggregate
-                                .ipv4
-                                .entry((timestamp, packet.proto, ipv4, dst_v4))
-                                .or_default()
+                            let src_key = source_key_v4(src_v4, route_table);
+                            aggregate.ipv4.observe(
+                                timestamp,
+                                packet.proto,
+                                src_key,
+                                src_v4,
+                                dst_v4,
+                                packet.tcp_flags,
+                            );
                         }
-                        IpAddr::V6(ipv6) => {
-                            let dst_v6 = if let IpAddr::V6(dst_v6) = dst_ip {
-                                dst_v6
+                        IpAddr::V6(dst_v6) => {
+                            let src_v6 = if let IpAddr::V6(src_v6) = packet.src_addr {
+                                src_v6
                             } else {
-                                panic!("Destination IP must be of same type as source ip.");
+                                panic!("Source IP must be of same type as destination ip.");
                             };
-                            aggregate
-                                .ipv6
-                                .entry((timestamp, packet.proto, ipv6, dst_v6))
-                                .or_default()
+                            let src_key = source_key_v6(src_v6, route_table);
+                            aggregate.ipv6.observe(
+                                timestamp,
+                                packet.proto,
+                                src_key,
+                                src_v6,
+                                dst_v6,
+                                packet.tcp_flags,
+                            );
                         }
                     };
-
-                    ipaggregate.total_packets += 1;
                 }
             }
 
@@ -183,17 +275,56 @@ fn aggregate_query_responses(
             nfdump.wait()?;
             unpack_file.wait()?;
 
-            Ok(aggregate)
+            Ok(aggregate.finish())
         })
         .try_reduce(Default::default, |result, aggregate| Ok(result + aggregate))?;
 
     Ok(aggregate)
 }
 
-pub fn ip_to_network_address(ip: IpAddr, cidrv4: u8, cidrv6: u8) -> IpAddr {
-    match ip {
-        IpAddr::V4(ipv4) => IpAddr::V4(ipv4_to_network_address(ipv4, cidrv4)),
-        IpAddr::V6(ipv6) => IpAddr::V6(ipv6_to_network_address(ipv6, cidrv6)),
+/// Key `addr` by its real BGP origin AS when `route_table` covers it, by the covering announced
+/// network when that route has no origin AS, and by the flat /24 mask otherwise.
+fn source_key_v4(addr: Ipv4Addr, route_table: Option<&RouteTable>) -> SourceKey<Ipv4Addr> {
+    if let Some((route, network)) =
+        route_table.and_then(|table| table.lookup_with_prefix(IpAddr::V4(addr)))
+    {
+        let origin_as = route.origin_as();
+        if origin_as != 0 {
+            return SourceKey::OriginAs(origin_as);
+        }
+        if let ipnetwork::IpNetwork::V4(network) = network {
+            return SourceKey::Network {
+                network: network.network(),
+                prefix_len: network.prefix(),
+            };
+        }
+    }
+    SourceKey::Network {
+        network: ipv4_to_network_address(addr, 24),
+        prefix_len: 24,
+    }
+}
+
+/// Key `addr` by its real BGP origin AS when `route_table` covers it, by the covering announced
+/// network when that route has no origin AS, and by the flat /48 mask otherwise.
+fn source_key_v6(addr: Ipv6Addr, route_table: Option<&RouteTable>) -> SourceKey<Ipv6Addr> {
+    if let Some((route, network)) =
+        route_table.and_then(|table| table.lookup_with_prefix(IpAddr::V6(addr)))
+    {
+        let origin_as = route.origin_as();
+        if origin_as != 0 {
+            return SourceKey::OriginAs(origin_as);
+        }
+        if let ipnetwork::IpNetwork::V6(network) = network {
+            return SourceKey::Network {
+                network: network.network(),
+                prefix_len: network.prefix(),
+            };
+        }
+    }
+    SourceKey::Network {
+        network: ipv6_to_network_address(addr, 48),
+        prefix_len: 48,
     }
 }
 
@@ -1,18 +1,42 @@
 #![recursion_limit = "512"]
 #![deny(unused_import_braces, unused_qualifications)]
 
+//! Stream an aggregate file straight into Postgres via a single binary `COPY FROM STDIN`.
+//!
+//! The previous importer read the whole file into a `String`, deserialized it into a
+//! `FullAggregate` in memory, then issued one `INSERT` per row: for a multi-day capture this
+//! means materializing both the raw JSON and the full row set before a single byte reaches the
+//! database, and paying a round trip per row. Instead, [`stream_parse_file`] drives a
+//! `serde_json::Deserializer` over a buffered reader with a hand-written `Visitor`, so no more
+//! than one row is ever held in memory at a time; each row is immediately binary-encoded
+//! ([`copy_binary`]) into a batch buffer that gets handed off to the `COPY` sink every
+//! [`COPY_BATCH_ROWS`] rows. Parsing runs on a blocking thread (serde's `Deserializer` is
+//! synchronous) and streams batches to the async task driving the `COPY` over an `mpsc` channel,
+//! so parsing the next batch overlaps with the previous one's network write.
+//!
+//! Rows are streamed to `COPY` in file order, with no sort or merge pass of their own, so this
+//! relies on aggregate files having already been merged once, at `netflow-aggregate` write time
+//! (`FamilyAggregateBuilder::finish`, then its `Serialize` impl): every
+//! `(timestamp, proto, src, dst)` key in a well-formed file is
+//! already unique. A hand-edited or concatenated file that violates this lands as separate,
+//! unsummed rows instead of being merged.
+
 use color_eyre::eyre::{Context as _, Result};
 use futures::stream;
 use futures::stream::{StreamExt, TryStreamExt};
-use netflow::aggregate::IpAggregate;
+use ipnetwork::IpNetwork;
+use netflow::aggregate::{IpAggregate, SourceKey};
 use netflow::Proto;
+use serde::de::{DeserializeSeed, Deserializer as _, MapAccess, SeqAccess, Visitor};
 use sqlx::postgres::PgConnectOptions;
 use sqlx::ConnectOptions;
-use std::net::IpAddr;
-use std::path::PathBuf;
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 use std::sync::Arc;
 use std::time::Duration;
+use tokio::sync::mpsc;
 
 #[derive(Debug, clap::Parser)]
 struct CliArgs {
@@ -52,89 +76,354 @@ async fn main() -> Result<()> {
     Ok(())
 }
 
+/// Rows to accumulate into one binary-encoded buffer before handing it to the `COPY` sink.
+const COPY_BATCH_ROWS: usize = 10_000;
+
+/// One flushed batch: the binary-encoded rows, plus enough context on its first/last source row
+/// to diagnose a `COPY` failure, mirroring the per-row `Key: ...` context the previous row-at-a-
+/// time importer attached to every failed `INSERT`.
+struct CopyBatch {
+    bytes: Vec<u8>,
+    rows: usize,
+    first_key: String,
+    last_key: String,
+}
+
 async fn process_file(
     pool: sqlx::postgres::PgPool,
     file: PathBuf,
     location: Arc<String>,
     agg_interval: i32,
 ) -> Result<()> {
-    let content = std::fs::read_to_string(file)?;
-    let mut deserializer = serde_json::Deserializer::from_str(&content);
-    let full_aggregate: netflow::aggregate::FullAggregate =
-        serde_path_to_error::deserialize(&mut deserializer)?;
+    let mut conn = pool.acquire().await?;
+    let mut copy =
+        conn.copy_in_raw("COPY nfaggregates FROM STDIN WITH (FORMAT binary)").await?;
+    copy.send(copy_binary::header()).await?;
 
-    #[allow(clippy::too_many_arguments)]
-    async fn insert_entry(
-        pool: &sqlx::postgres::PgPool,
-        location: &str,
-        agg_interval: i32,
+    let (tx, mut rx) = mpsc::channel::<CopyBatch>(4);
+    let parse_task = tokio::task::spawn_blocking({
+        let location = location.clone();
+        move || stream_parse_file(&file, &location, agg_interval, tx)
+    });
+
+    let mut total_rows: u64 = 0;
+    while let Some(batch) = rx.recv().await {
+        total_rows += batch.rows as u64;
+        copy.send(batch.bytes).await.wrap_err_with(|| {
+            format!(
+                "COPY batch of {} rows failed, first key: {}, last key: {}",
+                batch.rows, batch.first_key, batch.last_key
+            )
+        })?;
+    }
+    parse_task.await.context("Parser thread panicked")?.context("Failed to parse aggregate file")?;
+
+    copy.send(copy_binary::TRAILER.to_vec()).await?;
+    copy.finish().await?;
+    log::info!("Imported {total_rows} rows from {location}");
+    Ok(())
+}
+
+/// Stream-parse `file`'s `FullAggregate` JSON and feed every row, binary-encoded and batched, to
+/// `tx`. Runs on a blocking thread: `serde_json::Deserializer` has no async variant, so this
+/// keeps parsing off the tokio runtime's worker threads while it drives `tx.blocking_send`.
+fn stream_parse_file(
+    file: &Path,
+    location: &str,
+    agg_interval: i32,
+    tx: mpsc::Sender<CopyBatch>,
+) -> Result<()> {
+    let reader = std::io::BufReader::new(std::fs::File::open(file)?);
+    let mut sink =
+        RowSink { location, agg_interval, tx: &tx, buf: Vec::new(), rows: 0, first_row: None, last_row: None };
+
+    let mut de = serde_json::Deserializer::from_reader(reader);
+    let mut track = serde_path_to_error::Track::new();
+    let path_de = serde_path_to_error::Deserializer::new(&mut de, &mut track);
+    path_de
+        .deserialize_map(FullAggregateVisitor { sink: &mut sink })
+        .map_err(|err| serde_path_to_error::Error::new(track.path(), err))?;
+
+    sink.flush().map_err(|err| color_eyre::eyre::eyre!(err))?;
+    Ok(())
+}
+
+/// Accumulates binary-encoded rows for one file, flushing a [`CopyBatch`] to `tx` every
+/// [`COPY_BATCH_ROWS`] rows (and once more, whatever remains, when the caller calls
+/// [`flush`](Self::flush) after parsing finishes).
+struct RowSink<'a> {
+    location: &'a str,
+    agg_interval: i32,
+    tx: &'a mpsc::Sender<CopyBatch>,
+    buf: Vec<u8>,
+    rows: usize,
+    first_row: Option<RowContext>,
+    last_row: Option<RowContext>,
+}
+
+/// The fields identifying one row, kept around (cheaply, as a `Copy` struct) so the first/last
+/// row of a batch can be formatted into a diagnostic string lazily, only if that batch's `COPY`
+/// actually fails, rather than on every single row.
+#[derive(Clone, Copy)]
+struct RowContext {
+    ipnetwork_src: Option<IpNetwork>,
+    origin_as: Option<i64>,
+    time: u32,
+    proto: Proto,
+    ipnetwork_dst: IpNetwork,
+}
+
+impl RowContext {
+    fn describe(&self, location: &str, agg_interval: i32) -> String {
+        format!(
+            "{location}, {:?}, {:?}, {}, {agg_interval}, {}, {}",
+            self.ipnetwork_src, self.origin_as, self.time, self.proto.0, self.ipnetwork_dst
+        )
+    }
+}
+
+impl<'a> RowSink<'a> {
+    /// Encode one observed row and append it to the current batch, flushing early if the batch
+    /// is full. The source prefix and `origin_as` are mutually exclusive: a `Network`-keyed row
+    /// has an announced/masked source prefix but no resolved AS, an `OriginAs`-keyed row has the
+    /// reverse. The destination prefix is always set.
+    fn push(
+        &mut self,
         time: u32,
-        network_prefix: u8,
-        ip: IpAddr,
         proto: Proto,
-        ipnetwork_dst: IpAddr,
+        src: SourceKey<IpAddr>,
+        dst: IpAddr,
         data: IpAggregate,
-    ) -> Result<()> {
-        let ipnetwork_src = sqlx::types::ipnetwork::IpNetwork::new(ip, network_prefix)?;
-        let dst_prefix = match ipnetwork_dst {
+    ) -> Result<(), String> {
+        let (ipnetwork_src, origin_as) = match src {
+            SourceKey::Network { network, prefix_len } => {
+                (Some(IpNetwork::new(network, prefix_len).map_err(|err| err.to_string())?), None)
+            }
+            SourceKey::OriginAs(asn) => (None, Some(asn as i64)),
+        };
+        let dst_prefix = match dst {
             IpAddr::V4(_) => 32,
             IpAddr::V6(_) => 128,
         };
-        let ipnetwork_dst = sqlx::types::ipnetwork::IpNetwork::new(ipnetwork_dst, dst_prefix)?;
-        sqlx::query_unchecked!(
-            "INSERT INTO nfaggregates VALUES ($1, $2, $3, $4, $5, $6, $7);",
-            location,
+        let ipnetwork_dst = IpNetwork::new(dst, dst_prefix).map_err(|err| err.to_string())?;
+
+        let context = RowContext { ipnetwork_src, origin_as, time, proto, ipnetwork_dst };
+        self.first_row.get_or_insert(context);
+        self.last_row = Some(context);
+
+        copy_binary::encode_row(
+            &mut self.buf,
+            self.location,
             ipnetwork_src,
+            origin_as,
             time as i32,
-            agg_interval,
+            self.agg_interval,
             proto.0 as i16,
             ipnetwork_dst,
-            // general fields
             data.total_packets as i32,
-        )
-        .execute(pool)
-        .await
-        .wrap_err_with(|| {
-            format!(
-                "Key: {}, {}, {}, {}, {}, {}",
-                location, ipnetwork_src, time, agg_interval, proto.0, ipnetwork_dst
-            )
-        })?;
+        );
+        self.rows += 1;
+
+        if self.rows >= COPY_BATCH_ROWS {
+            self.flush()?;
+        }
         Ok(())
     }
 
-    stream::iter(full_aggregate.ipv4.into_iter())
-        .map(Ok)
-        .try_for_each_concurrent(10, |((time, proto, ip_src, ipnetwork_dst), data)| {
-            insert_entry(
-                &pool,
-                &location,
-                agg_interval,
-                time,
-                24,
-                ip_src.into(),
-                proto,
-                ipnetwork_dst.into(),
-                data,
-            )
-        })
-        .await?;
+    /// Send whatever rows have accumulated since the last flush, if any.
+    fn flush(&mut self) -> Result<(), String> {
+        if self.rows == 0 {
+            return Ok(());
+        }
+        let first_row = self.first_row.take().expect("set alongside rows");
+        let last_row = self.last_row.take().expect("set alongside rows");
+        let batch = CopyBatch {
+            bytes: std::mem::take(&mut self.buf),
+            rows: self.rows,
+            first_key: first_row.describe(self.location, self.agg_interval),
+            last_key: last_row.describe(self.location, self.agg_interval),
+        };
+        self.rows = 0;
+        self.tx.blocking_send(batch).map_err(|_| "COPY receiver dropped".to_owned())
+    }
+}
 
-    stream::iter(full_aggregate.ipv6.into_iter())
-        .map(Ok)
-        .try_for_each_concurrent(10, |((time, proto, ip_src, ipnetwork_dst), data)| {
-            insert_entry(
-                &pool,
-                &location,
-                agg_interval,
-                time,
-                48,
-                ip_src.into(),
-                proto,
-                ipnetwork_dst.into(),
-                data,
-            )
-        })
-        .await?;
-    Ok(())
+/// Streams one address family's row array (`[((time, proto, src, dst), data), ...]`) straight
+/// into a [`RowSink`] without ever collecting it into a `Vec`.
+struct FamilySeed<'a, 'b, A> {
+    sink: &'a mut RowSink<'b>,
+    widen: fn(A) -> IpAddr,
+}
+
+impl<'de, 'a, 'b, A> DeserializeSeed<'de> for FamilySeed<'a, 'b, A>
+where
+    A: serde::Deserialize<'de> + Copy,
+{
+    type Value = ();
+
+    fn deserialize<D: serde::Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de, 'a, 'b, A> Visitor<'de> for FamilySeed<'a, 'b, A>
+where
+    A: serde::Deserialize<'de> + Copy,
+{
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "an array of aggregate entries")
+    }
+
+    fn visit_seq<S: SeqAccess<'de>>(self, mut seq: S) -> Result<Self::Value, S::Error> {
+        while let Some(((time, proto, src, dst), data)) =
+            seq.next_element::<((u32, Proto, SourceKey<A>, A), IpAggregate)>()?
+        {
+            let dst = (self.widen)(dst);
+            let src = src.map_network(self.widen);
+            self.sink.push(time, proto, src, dst, data).map_err(serde::de::Error::custom)?;
+        }
+        Ok(())
+    }
+}
+
+/// Top-level streaming visitor for a `FullAggregate`'s `{"ipv4": [...], "ipv6": [...]}` shape.
+struct FullAggregateVisitor<'a, 'b> {
+    sink: &'a mut RowSink<'b>,
+}
+
+impl<'de, 'a, 'b> Visitor<'de> for FullAggregateVisitor<'a, 'b> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a FullAggregate object with ipv4/ipv6 fields")
+    }
+
+    fn visit_map<M: MapAccess<'de>>(self, mut map: M) -> Result<Self::Value, M::Error> {
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "ipv4" => {
+                    map.next_value_seed(FamilySeed::<Ipv4Addr> {
+                        sink: &mut *self.sink,
+                        widen: IpAddr::V4,
+                    })?;
+                }
+                "ipv6" => {
+                    map.next_value_seed(FamilySeed::<Ipv6Addr> {
+                        sink: &mut *self.sink,
+                        widen: IpAddr::V6,
+                    })?;
+                }
+                _ => {
+                    map.next_value::<serde::de::IgnoredAny>()?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Binary `COPY` row encoding for the `nfaggregates` table, in the on-wire format documented at
+/// <https://www.postgresql.org/docs/current/sql-copy.html#id-1.9.3.55.9.4>.
+mod copy_binary {
+    use ipnetwork::IpNetwork;
+
+    const SIGNATURE: &[u8; 11] = b"PGCOPY\n\xff\r\n\0";
+
+    /// File header: the fixed signature, no flags, no header extension.
+    pub fn header() -> Vec<u8> {
+        let mut buf = Vec::with_capacity(SIGNATURE.len() + 8);
+        buf.extend_from_slice(SIGNATURE);
+        buf.extend_from_slice(&0i32.to_be_bytes()); // flags
+        buf.extend_from_slice(&0i32.to_be_bytes()); // header extension length
+        buf
+    }
+
+    /// File trailer: a field count of `-1` instead of a row.
+    pub const TRAILER: [u8; 2] = (-1i16).to_be_bytes();
+
+    /// Append one `nfaggregates` row, with its eight columns in this order: location, source
+    /// network, origin AS, time, aggregation interval, protocol, destination network, total
+    /// packets.
+    #[allow(clippy::too_many_arguments)]
+    pub fn encode_row(
+        buf: &mut Vec<u8>,
+        location: &str,
+        ipnetwork_src: Option<IpNetwork>,
+        origin_as: Option<i64>,
+        time: i32,
+        agg_interval: i32,
+        proto: i16,
+        ipnetwork_dst: IpNetwork,
+        total_packets: i32,
+    ) {
+        buf.extend_from_slice(&8i16.to_be_bytes());
+        push_text(buf, location);
+        push_network_opt(buf, ipnetwork_src);
+        push_i64_opt(buf, origin_as);
+        push_i32(buf, time);
+        push_i32(buf, agg_interval);
+        push_i16(buf, proto);
+        push_network(buf, ipnetwork_dst);
+        push_i32(buf, total_packets);
+    }
+
+    fn push_field(buf: &mut Vec<u8>, bytes: Option<&[u8]>) {
+        match bytes {
+            Some(bytes) => {
+                buf.extend_from_slice(&(bytes.len() as i32).to_be_bytes());
+                buf.extend_from_slice(bytes);
+            }
+            None => buf.extend_from_slice(&(-1i32).to_be_bytes()),
+        }
+    }
+
+    fn push_text(buf: &mut Vec<u8>, value: &str) {
+        push_field(buf, Some(value.as_bytes()));
+    }
+
+    fn push_i16(buf: &mut Vec<u8>, value: i16) {
+        push_field(buf, Some(&value.to_be_bytes()));
+    }
+
+    fn push_i32(buf: &mut Vec<u8>, value: i32) {
+        push_field(buf, Some(&value.to_be_bytes()));
+    }
+
+    fn push_i64_opt(buf: &mut Vec<u8>, value: Option<i64>) {
+        push_field(buf, value.map(i64::to_be_bytes).as_ref().map(|bytes| bytes.as_slice()));
+    }
+
+    /// Postgres' on-wire `PGSQL_AF_INET`/`PGSQL_AF_INET6` family tags for the `cidr` binary
+    /// format (`src/include/utils/inet.h`): these deliberately don't reuse the platform's own
+    /// `AF_INET`/`AF_INET6` values, so they can't be taken from `std::net`.
+    fn network_family(network: IpNetwork) -> u8 {
+        match network {
+            IpNetwork::V4(_) => 2,
+            IpNetwork::V6(_) => 3,
+        }
+    }
+
+    fn push_network(buf: &mut Vec<u8>, network: IpNetwork) {
+        let mut encoded = vec![network_family(network), network.prefix(), /* is_cidr */ 1];
+        match network {
+            IpNetwork::V4(network) => {
+                encoded.push(4);
+                encoded.extend_from_slice(&network.network().octets());
+            }
+            IpNetwork::V6(network) => {
+                encoded.push(16);
+                encoded.extend_from_slice(&network.network().octets());
+            }
+        }
+        push_field(buf, Some(&encoded));
+    }
+
+    fn push_network_opt(buf: &mut Vec<u8>, network: Option<IpNetwork>) {
+        match network {
+            Some(network) => push_network(buf, network),
+            None => push_field(buf, None),
+        }
+    }
 }
@@ -3,9 +3,15 @@
 #![deny(unused_import_braces, unused_qualifications)]
 
 pub mod aggregate;
+pub mod bgp;
+pub mod target_set;
+pub mod wire;
 
+use std::fmt::Write as _;
 use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 
+pub use target_set::TargetSet;
+
 /// The IP address can be with all bits.
 /// `tcp_flags` contains the TCP flags in string from, like `A` or `P`.
 ///
@@ -190,8 +196,8 @@ impl Proto {
 #[serde(transparent)]
 pub struct ForwardStatus(u8);
 
-#[derive(Debug, serde_with::DeserializeFromStr)]
-pub struct TcpFlags(u8);
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, serde_with::DeserializeFromStr)]
+pub struct TcpFlags(pub u8);
 
 impl TcpFlags {
     pub const FIN: Self = Self(0x01);
@@ -202,6 +208,30 @@ impl TcpFlags {
     pub const URG: Self = Self(0x20);
     pub const ECE: Self = Self(0x40);
     pub const CWR: Self = Self(0x80);
+
+    /// Return `true` if every flag bit set in `flag` is also set in `self`.
+    pub fn has(self, flag: Self) -> bool {
+        self.0 & flag.0 == flag.0
+    }
+}
+
+impl std::fmt::Display for TcpFlags {
+    /// Format back to the dotted 8-character form nfdump uses, e.g. ACK, PSH and SYN set -> `...AP.S.`.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (flag, c) in [
+            (Self::CWR, 'C'),
+            (Self::ECE, 'E'),
+            (Self::URG, 'U'),
+            (Self::ACK, 'A'),
+            (Self::PSH, 'P'),
+            (Self::RST, 'R'),
+            (Self::SYN, 'S'),
+            (Self::FIN, 'F'),
+        ] {
+            f.write_char(if self.has(flag) { c } else { '.' })?;
+        }
+        Ok(())
+    }
 }
 
 impl std::str::FromStr for TcpFlags {
@@ -238,20 +268,23 @@ pub enum Direction {
 }
 
 /// Return `true` is the flow targets a nameserver of our ccTLD
-pub fn is_for_target_cctld(flow: &NfdumpOutput) -> bool {
+pub fn is_for_target_cctld(flow: &NfdumpOutput, targets: &TargetSet) -> bool {
     if flow.dst_port != Some(53) {
         return false;
     }
     if !matches!(flow.proto, Proto::TCP | Proto::UDP) {
         return false;
     }
-    if let Some(_dst4_addr) = flow.dst4_addr {
-        // TODO: Filter here for the IPv4 addresses of the ccTLD
-    } else if let Some(_dst6_addr) = flow.dst6_addr {
-        // TODO: Filter here for the IPv6 addresses of the ccTLD
-    }
 
-    true
+    let dst_addr: IpAddr = if let Some(dst4_addr) = flow.dst4_addr {
+        dst4_addr.into()
+    } else if let Some(dst6_addr) = flow.dst6_addr {
+        dst6_addr.into()
+    } else {
+        return false;
+    };
+
+    targets.contains(dst_addr)
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
@@ -261,6 +294,8 @@ pub struct Packet {
     pub proto: Proto,
     pub bytes: u64,
     pub time: chrono::NaiveDateTime,
+    /// The flow's TCP flags, as reported by nfdump. `0` (none set) for non-TCP flows.
+    pub tcp_flags: TcpFlags,
 }
 
 /// Split one flow into multiple equal spaced packets
@@ -287,6 +322,7 @@ pub fn split_flow(flow: NfdumpOutput) -> impl Iterator<Item = Packet> {
         panic!("no dst addr");
     };
     let proto = flow.proto;
+    let tcp_flags = flow.tcp_flags;
     let num_packets = flow.in_packets;
     let total_bytes = flow.in_bytes;
     let mut time_start = flow.t_first;
@@ -298,6 +334,7 @@ pub fn split_flow(flow: NfdumpOutput) -> impl Iterator<Item = Packet> {
             proto,
             bytes: total_bytes,
             time: time_start,
+            tcp_flags,
         }]
         .into_iter()
     } else {
@@ -312,6 +349,7 @@ pub fn split_flow(flow: NfdumpOutput) -> impl Iterator<Item = Packet> {
             proto,
             bytes: bytes_per_packet + extra_bytes,
             time: time_start,
+            tcp_flags,
         };
 
         let mut res = Vec::with_capacity(num_packets as usize);
@@ -324,6 +362,7 @@ pub fn split_flow(flow: NfdumpOutput) -> impl Iterator<Item = Packet> {
                 proto,
                 bytes: bytes_per_packet,
                 time: time_start,
+                tcp_flags,
             };
             res.push(packet);
         }
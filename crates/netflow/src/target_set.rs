@@ -0,0 +1,141 @@
+//! Longest-prefix-match address sets.
+//!
+//! Used to classify whether a destination address belongs to a configured set
+//! of prefixes, e.g. the authoritative-nameserver ranges of the protected
+//! ccTLD.
+
+use std::net::IpAddr;
+
+/// A prefix entry, packed as a fixed-size address plus a prefix length so a
+/// large allowlist of prefixes stays cache-friendly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct PackedPrefix<const N: usize> {
+    addr: [u8; N],
+    pfxlen: u8,
+}
+
+impl<const N: usize> PackedPrefix<N> {
+    fn bit(&self, index: u8) -> usize {
+        let byte = self.addr[(index / 8) as usize];
+        ((byte >> (7 - (index % 8))) & 1) as usize
+    }
+}
+
+/// A binary Patricia/radix trie for longest-prefix matching over `N`-byte addresses.
+#[derive(Debug, Default)]
+struct PrefixTrie<const N: usize> {
+    root: TrieNode,
+}
+
+#[derive(Debug, Default)]
+struct TrieNode {
+    /// Set when a prefix ends exactly at this node.
+    is_prefix_end: bool,
+    children: [Option<Box<TrieNode>>; 2],
+}
+
+impl<const N: usize> PrefixTrie<N> {
+    fn insert(&mut self, prefix: PackedPrefix<N>) {
+        let mut node = &mut self.root;
+        for i in 0..prefix.pfxlen {
+            node = node.children[prefix.bit(i)].get_or_insert_with(Default::default);
+        }
+        node.is_prefix_end = true;
+    }
+
+    fn contains(&self, addr: &[u8; N]) -> bool {
+        let mut node = &self.root;
+        if node.is_prefix_end {
+            return true;
+        }
+        for i in 0..(N as u8 * 8) {
+            let byte = addr[(i / 8) as usize];
+            let bit = ((byte >> (7 - (i % 8))) & 1) as usize;
+            match &node.children[bit] {
+                Some(child) => {
+                    node = child;
+                    if node.is_prefix_end {
+                        return true;
+                    }
+                }
+                None => return false,
+            }
+        }
+        false
+    }
+}
+
+/// Longest-prefix-match set of target prefixes, e.g. the authoritative
+/// nameserver ranges of the protected ccTLD.
+#[derive(Debug, Default)]
+pub struct TargetSet {
+    v4: PrefixTrie<4>,
+    v6: PrefixTrie<16>,
+}
+
+impl TargetSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Insert a CIDR string such as `"198.51.100.0/24"` or `"2001:db8::/32"`.
+    pub fn insert(&mut self, cidr: &str) -> Result<(), String> {
+        let (addr, pfxlen) = cidr
+            .split_once('/')
+            .ok_or_else(|| format!("invalid CIDR, missing '/': {cidr}"))?;
+        let pfxlen: u8 = pfxlen
+            .parse()
+            .map_err(|_| format!("invalid prefix length in CIDR: {cidr}"))?;
+        let addr: IpAddr = addr
+            .parse()
+            .map_err(|_| format!("invalid address in CIDR: {cidr}"))?;
+
+        match addr {
+            IpAddr::V4(addr) => {
+                if pfxlen > 32 {
+                    return Err(format!("IPv4 prefix length out of range: {cidr}"));
+                }
+                self.v4.insert(PackedPrefix {
+                    addr: addr.octets(),
+                    pfxlen,
+                });
+            }
+            IpAddr::V6(addr) => {
+                if pfxlen > 128 {
+                    return Err(format!("IPv6 prefix length out of range: {cidr}"));
+                }
+                self.v6.insert(PackedPrefix {
+                    addr: addr.octets(),
+                    pfxlen,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Load a `TargetSet` from a list of CIDR strings.
+    pub fn from_cidrs<'a>(cidrs: impl IntoIterator<Item = &'a str>) -> Result<Self, String> {
+        let mut set = Self::new();
+        for cidr in cidrs {
+            set.insert(cidr)?;
+        }
+        Ok(set)
+    }
+
+    /// Return `true` only if `addr` is covered by some prefix in the set.
+    pub fn contains(&self, addr: IpAddr) -> bool {
+        match addr {
+            IpAddr::V4(addr) => self.v4.contains(&addr.octets()),
+            IpAddr::V6(addr) => self.v6.contains(&addr.octets()),
+        }
+    }
+}
+
+#[test]
+fn test_target_set_longest_prefix_match() {
+    let set = TargetSet::from_cidrs(["198.51.100.0/24", "2001:db8::/32"]).unwrap();
+    assert!(set.contains("198.51.100.53".parse().unwrap()));
+    assert!(!set.contains("203.0.113.53".parse().unwrap()));
+    assert!(set.contains("2001:db8::53".parse().unwrap()));
+    assert!(!set.contains("2001:db9::53".parse().unwrap()));
+}
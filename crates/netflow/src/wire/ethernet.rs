@@ -0,0 +1,38 @@
+//! Ethernet II frame header.
+
+use super::{truncated, DecodeError};
+
+pub const ETHERTYPE_IPV4: u16 = 0x0800;
+pub const ETHERTYPE_IPV6: u16 = 0x86dd;
+
+/// A checked view over an Ethernet II frame.
+pub struct EthernetView<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> EthernetView<'a> {
+    pub const HEADER_LEN: usize = 14;
+
+    pub fn new(bytes: &'a [u8]) -> Result<Self, DecodeError> {
+        if bytes.len() < Self::HEADER_LEN {
+            return Err(truncated("ethernet"));
+        }
+        Ok(Self { bytes })
+    }
+
+    pub fn dst_mac(&self) -> [u8; 6] {
+        self.bytes[0..6].try_into().expect("checked in new")
+    }
+
+    pub fn src_mac(&self) -> [u8; 6] {
+        self.bytes[6..12].try_into().expect("checked in new")
+    }
+
+    pub fn ethertype(&self) -> u16 {
+        u16::from_be_bytes([self.bytes[12], self.bytes[13]])
+    }
+
+    pub fn payload(&self) -> &'a [u8] {
+        &self.bytes[Self::HEADER_LEN..]
+    }
+}
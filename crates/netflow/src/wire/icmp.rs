@@ -0,0 +1,34 @@
+//! ICMP(v4/v6) header.
+//!
+//! Both families share the same leading `type, code, checksum` layout, which
+//! is all the downstream `Packet` model needs.
+
+use super::{truncated, DecodeError};
+
+/// A checked view over an ICMP/ICMPv6 header.
+pub struct IcmpView<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> IcmpView<'a> {
+    pub const HEADER_LEN: usize = 4;
+
+    pub fn new(bytes: &'a [u8]) -> Result<Self, DecodeError> {
+        if bytes.len() < Self::HEADER_LEN {
+            return Err(truncated("icmp"));
+        }
+        Ok(Self { bytes })
+    }
+
+    pub fn icmp_type(&self) -> u8 {
+        self.bytes[0]
+    }
+
+    pub fn code(&self) -> u8 {
+        self.bytes[1]
+    }
+
+    pub fn payload(&self) -> &'a [u8] {
+        &self.bytes[Self::HEADER_LEN..]
+    }
+}
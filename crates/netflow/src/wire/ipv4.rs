@@ -0,0 +1,62 @@
+//! IPv4 header.
+
+use std::net::Ipv4Addr;
+
+use super::{truncated, DecodeError};
+use crate::Proto;
+
+/// A checked view over an IPv4 header.
+pub struct Ipv4View<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Ipv4View<'a> {
+    pub const MIN_HEADER_LEN: usize = 20;
+
+    pub fn new(bytes: &'a [u8]) -> Result<Self, DecodeError> {
+        if bytes.len() < Self::MIN_HEADER_LEN {
+            return Err(truncated("ipv4"));
+        }
+        let version = bytes[0] >> 4;
+        if version != 4 {
+            return Err(DecodeError(format!("unsupported IP version {version}, expected 4")));
+        }
+        let ihl = (bytes[0] & 0x0f) as usize * 4;
+        if ihl < Self::MIN_HEADER_LEN || bytes.len() < ihl {
+            return Err(truncated("ipv4"));
+        }
+        Ok(Self { bytes })
+    }
+
+    fn ihl(&self) -> usize {
+        (self.bytes[0] & 0x0f) as usize * 4
+    }
+
+    pub fn total_length(&self) -> u16 {
+        u16::from_be_bytes([self.bytes[2], self.bytes[3]])
+    }
+
+    pub fn protocol(&self) -> Proto {
+        Proto(self.bytes[9])
+    }
+
+    pub fn src_addr(&self) -> Ipv4Addr {
+        Ipv4Addr::new(self.bytes[12], self.bytes[13], self.bytes[14], self.bytes[15])
+    }
+
+    pub fn dst_addr(&self) -> Ipv4Addr {
+        Ipv4Addr::new(self.bytes[16], self.bytes[17], self.bytes[18], self.bytes[19])
+    }
+
+    /// The transport-layer payload, bounded by `total_length` rather than the
+    /// captured slice length (the capture may include link-layer padding).
+    pub fn payload(&self) -> &'a [u8] {
+        let ihl = self.ihl();
+        let end = (self.total_length() as usize).min(self.bytes.len());
+        if ihl >= end {
+            &[]
+        } else {
+            &self.bytes[ihl..end]
+        }
+    }
+}
@@ -0,0 +1,51 @@
+//! IPv6 fixed header.
+//!
+//! Extension headers are not walked; `next_header` is reported as-is from the
+//! fixed header, matching what the rest of the crate needs (the transport
+//! protocol for the common no-extension-header case).
+
+use std::net::Ipv6Addr;
+
+use super::{truncated, DecodeError};
+use crate::Proto;
+
+/// A checked view over the IPv6 fixed header.
+pub struct Ipv6View<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Ipv6View<'a> {
+    pub const HEADER_LEN: usize = 40;
+
+    pub fn new(bytes: &'a [u8]) -> Result<Self, DecodeError> {
+        if bytes.len() < Self::HEADER_LEN {
+            return Err(truncated("ipv6"));
+        }
+        let version = bytes[0] >> 4;
+        if version != 6 {
+            return Err(DecodeError(format!("unsupported IP version {version}, expected 6")));
+        }
+        Ok(Self { bytes })
+    }
+
+    pub fn payload_length(&self) -> u16 {
+        u16::from_be_bytes([self.bytes[4], self.bytes[5]])
+    }
+
+    pub fn next_header(&self) -> Proto {
+        Proto(self.bytes[6])
+    }
+
+    pub fn src_addr(&self) -> Ipv6Addr {
+        Ipv6Addr::from(<[u8; 16]>::try_from(&self.bytes[8..24]).expect("checked in new"))
+    }
+
+    pub fn dst_addr(&self) -> Ipv6Addr {
+        Ipv6Addr::from(<[u8; 16]>::try_from(&self.bytes[24..40]).expect("checked in new"))
+    }
+
+    pub fn payload(&self) -> &'a [u8] {
+        let end = (Self::HEADER_LEN + self.payload_length() as usize).min(self.bytes.len());
+        &self.bytes[Self::HEADER_LEN..end]
+    }
+}
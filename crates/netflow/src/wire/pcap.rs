@@ -0,0 +1,78 @@
+//! Minimal reader for the classic pcap capture file format.
+//!
+//! Only the `libpcap` (not pcapng) container is supported: a 24-byte global
+//! header followed by a sequence of `(record header, packet bytes)` pairs.
+
+use super::{truncated, DecodeError};
+
+const MAGIC_LE: u32 = 0xa1b2_c3d4;
+const MAGIC_SWAPPED: u32 = 0xd4c3_b2a1;
+const GLOBAL_HEADER_LEN: usize = 24;
+const RECORD_HEADER_LEN: usize = 16;
+
+/// Iterates over the `(timestamp, packet bytes)` records of a pcap buffer.
+pub struct PcapReader<'a> {
+    data: &'a [u8],
+    swapped: bool,
+}
+
+impl<'a> PcapReader<'a> {
+    pub fn new(data: &'a [u8]) -> Result<Self, DecodeError> {
+        if data.len() < GLOBAL_HEADER_LEN {
+            return Err(truncated("pcap global"));
+        }
+        let magic = u32::from_le_bytes(data[0..4].try_into().expect("checked length"));
+        let swapped = match magic {
+            MAGIC_LE => false,
+            MAGIC_SWAPPED => true,
+            other => return Err(DecodeError(format!("unrecognized pcap magic {other:#010x}"))),
+        };
+        Ok(Self {
+            data: &data[GLOBAL_HEADER_LEN..],
+            swapped,
+        })
+    }
+
+    fn read_u32(&self, bytes: &[u8]) -> u32 {
+        let raw = u32::from_le_bytes(bytes.try_into().expect("checked length"));
+        if self.swapped {
+            raw.swap_bytes()
+        } else {
+            raw
+        }
+    }
+}
+
+impl<'a> Iterator for PcapReader<'a> {
+    type Item = Result<(chrono::NaiveDateTime, &'a [u8]), DecodeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.data.is_empty() {
+            return None;
+        }
+        if self.data.len() < RECORD_HEADER_LEN {
+            return Some(Err(truncated("pcap record")));
+        }
+
+        let ts_sec = self.read_u32(&self.data[0..4]);
+        let ts_usec = self.read_u32(&self.data[4..8]);
+        let incl_len = self.read_u32(&self.data[8..12]) as usize;
+        self.data = &self.data[RECORD_HEADER_LEN..];
+
+        if self.data.len() < incl_len {
+            return Some(Err(truncated("pcap record data")));
+        }
+        let (packet, rest) = self.data.split_at(incl_len);
+        self.data = rest;
+
+        let time = match chrono::NaiveDateTime::from_timestamp_opt(
+            ts_sec as i64,
+            ts_usec.saturating_mul(1000),
+        ) {
+            Some(time) => time,
+            None => return Some(Err(DecodeError("invalid pcap record timestamp".to_string()))),
+        };
+
+        Some(Ok((time, packet)))
+    }
+}
@@ -0,0 +1,44 @@
+//! TCP header.
+
+use super::{truncated, DecodeError};
+
+/// A checked view over a TCP header.
+pub struct TcpView<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> TcpView<'a> {
+    pub const MIN_HEADER_LEN: usize = 20;
+
+    pub fn new(bytes: &'a [u8]) -> Result<Self, DecodeError> {
+        if bytes.len() < Self::MIN_HEADER_LEN {
+            return Err(truncated("tcp"));
+        }
+        let data_offset = (bytes[12] >> 4) as usize * 4;
+        if data_offset < Self::MIN_HEADER_LEN || bytes.len() < data_offset {
+            return Err(truncated("tcp"));
+        }
+        Ok(Self { bytes })
+    }
+
+    fn data_offset(&self) -> usize {
+        (self.bytes[12] >> 4) as usize * 4
+    }
+
+    pub fn src_port(&self) -> u16 {
+        u16::from_be_bytes([self.bytes[0], self.bytes[1]])
+    }
+
+    pub fn dst_port(&self) -> u16 {
+        u16::from_be_bytes([self.bytes[2], self.bytes[3]])
+    }
+
+    /// Raw flag byte, in the same bit layout as [`crate::TcpFlags`].
+    pub fn flags(&self) -> u8 {
+        self.bytes[13]
+    }
+
+    pub fn payload(&self) -> &'a [u8] {
+        &self.bytes[self.data_offset()..]
+    }
+}
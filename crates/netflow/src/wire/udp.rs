@@ -0,0 +1,36 @@
+//! UDP header.
+
+use super::{truncated, DecodeError};
+
+/// A checked view over a UDP header.
+pub struct UdpView<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> UdpView<'a> {
+    pub const HEADER_LEN: usize = 8;
+
+    pub fn new(bytes: &'a [u8]) -> Result<Self, DecodeError> {
+        if bytes.len() < Self::HEADER_LEN {
+            return Err(truncated("udp"));
+        }
+        Ok(Self { bytes })
+    }
+
+    pub fn src_port(&self) -> u16 {
+        u16::from_be_bytes([self.bytes[0], self.bytes[1]])
+    }
+
+    pub fn dst_port(&self) -> u16 {
+        u16::from_be_bytes([self.bytes[2], self.bytes[3]])
+    }
+
+    pub fn length(&self) -> u16 {
+        u16::from_be_bytes([self.bytes[4], self.bytes[5]])
+    }
+
+    pub fn payload(&self) -> &'a [u8] {
+        let end = (self.length() as usize).min(self.bytes.len());
+        &self.bytes[Self::HEADER_LEN..end.max(Self::HEADER_LEN)]
+    }
+}